@@ -0,0 +1,47 @@
+//! Request parsing throughput for a large POST body, fed the way a socket
+//! delivers it: byte by byte, as the server used to read, or in 4 KiB reads.
+//!
+//! `cargo bench` reports MB/s for each.
+
+#![feature(test)]
+
+extern crate test;
+extern crate core_mini_http;
+
+use core_mini_http::*;
+use test::Bencher;
+
+const BODY_SIZE: usize = 256 * 1024;
+
+fn post_request() -> Vec<u8> {
+    let mut msg = format!("POST /upload HTTP/1.1\r\nHost: example.com\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\n\r\n", BODY_SIZE).into_bytes();
+    for i in 0..BODY_SIZE {
+        msg.push((i % 251) as u8);
+    }
+    msg
+}
+
+fn parse_in_chunks(msg: &[u8], size: usize) -> HttpRequestMessage {
+    let mut limits = ParserLimits::default();
+    limits.max_body_size = BODY_SIZE;
+
+    let mut parser = HttpRequestParser::with_limits(limits);
+    for chunk in msg.chunks(size) {
+        parser.parse_bytes(chunk).unwrap();
+    }
+    parser.take_request().unwrap()
+}
+
+#[bench]
+fn bench_post_byte_reads(b: &mut Bencher) {
+    let msg = post_request();
+    b.bytes = msg.len() as u64;
+    b.iter(|| parse_in_chunks(&msg, 1));
+}
+
+#[bench]
+fn bench_post_4k_reads(b: &mut Bencher) {
+    let msg = post_request();
+    b.bytes = msg.len() as u64;
+    b.iter(|| parse_in_chunks(&msg, 4096));
+}
@@ -1,70 +1,43 @@
-use std::net::{TcpListener, TcpStream};
-use std::thread;
-use std::io::{Read, Write};
-use std::net::Shutdown;
-
 extern crate core_mini_http;
 
 use core_mini_http::*;
-use std::sync::Arc;
-
-struct HttpServer {
-    routes: Vec<Box<HttpRoute + Send + Sync + 'static>>,
-}
-
-impl HttpServer {
-    fn handle_client(&self, stream: TcpStream) {
-        let mut stream = stream;
 
-        let mut parser = HttpRequestParser::new();
-
-        loop {
-            let mut buf = [0; 1];
-            let r = stream.read(&mut buf);
-            if !r.is_ok() {
-                panic!("stream broken");
-            }
-
-            if r.unwrap() == 0 {
-                println!("stream endeth");
-                break;
-            }
-
-            let parsed = parser.parse_bytes(&buf);
-            if !parsed.is_ok() {
-                panic!("parser borked");
-            }
-
-            if parser.read_how_many_bytes() == 0 {
-                break;
-            }
+fn run_client(args: &[String]) {
+    let res = match (args.get(1).map(|s| &s[..]), args.get(2)) {
+        (Some("get"), Some(url)) => client::get(url),
+        (Some("post"), Some(url)) => {
+            let body = args.get(3).map(|s| &s[..]).unwrap_or("");
+            let mut headers = HeaderMap::new();
+            headers.insert("Content-Type", "application/x-www-form-urlencoded");
+            client::post(url, body.as_bytes(), &headers)
+        },
+        _ => {
+            println!("usage: {} [get <url> | post <url> [body]]", args[0]);
+            return;
         }
+    };
 
-        println!("{:?}", parser.get_request());
-
-        stream.shutdown(Shutdown::Read).unwrap();
-
-        let res = http_router(&self.routes, &parser.get_request());
-        if res.is_ok() {
-            let resp = res.unwrap().execute(&parser.get_request());
-            if resp.is_ok() {
-                let resp = resp.unwrap();
-                stream.write(&resp.to_bytes()).unwrap();
-                stream.flush().unwrap();
-                stream.shutdown(Shutdown::Write).unwrap();
-                return;
+    match res {
+        Ok(resp) => {
+            println!("HTTP/{} {} {}", resp.http_version, resp.response_code, resp.response_status);
+            for (key, val) in &resp.headers {
+                println!("{}: {}", key, val);
             }
-        }
-
-        stream.shutdown(Shutdown::Write).unwrap();
+            println!("");
+            println!("{}", String::from_utf8_lossy(&resp.body));
+        },
+        Err(e) => println!("request failed: {:?}", e)
     }
 }
 
-
 fn main() {
-    let listener = TcpListener::bind("127.0.0.1:8088").unwrap();
-    let server = HttpServer {
-        routes: vec![
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() > 1 {
+        run_client(&args);
+        return;
+    }
+
+    let routes: Vec<Box<HttpRoute + Send + Sync + 'static>> = vec![
             Box::new(HttpRouteStaticUrl::new_get("/", |_| {
                 HttpResponseMessage::html_utf8("<h1>Hello World!</h1><form method='post' action='/form'><p>ssid: <input type='text' name='ssid' value='' /></p><p><input type='submit' name='submit' value='Connect' /></p></form>")
             })),
@@ -93,22 +66,363 @@ fn main() {
                     HttpResponseMessage::html_utf8(&format!("<h1>Hello World!</h1><p>ID: <b>{}</b></p>", vars.get("id").unwrap()))
                 })),
 
-                    ]
-    };
-    let server = Arc::new(server);
-
-    // accept connections and process them, spawning a new thread for each one
-    for stream in listener.incoming() {
-        let server = server.clone();
-        match stream {
-            Ok(stream) => {
-                thread::spawn(move|| {
-                    // connection succeeded
-                    //handle_client(stream);                    
-                    server.handle_client(stream);
-                });
+                    ];
+
+    HttpServer::new(routes).bind("127.0.0.1:8088").unwrap().serve().unwrap();
+}
+
+/// A small blocking client, for tests and command line tools talking to local servers.
+mod client {
+    use std::io;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    use core_mini_http::*;
+
+    #[derive(Debug)]
+    pub enum ClientError {
+        /// Only plain `http://host[:port]/path` URLs are supported.
+        InvalidUrl,
+        Io(io::Error),
+        Response(HttpResponseParserError),
+        TooManyRedirects,
+    }
+
+    impl From<io::Error> for ClientError {
+        fn from(e: io::Error) -> ClientError {
+            ClientError::Io(e)
+        }
+    }
+
+    pub struct HttpClient {
+        pub timeout: Duration,
+        pub max_redirects: u8,
+    }
+
+    pub fn get(url: &str) -> Result<HttpResponseMessage, ClientError> {
+        HttpClient::new().request(HttpMethod::Get, url, &[], &HeaderMap::new())
+    }
+
+    pub fn post(url: &str, body: &[u8], headers: &HeaderMap) -> Result<HttpResponseMessage, ClientError> {
+        HttpClient::new().request(HttpMethod::Post, url, body, headers)
+    }
+
+    impl HttpClient {
+        pub fn new() -> HttpClient {
+            HttpClient {
+                timeout: Duration::from_secs(10),
+                max_redirects: 5
+            }
+        }
+
+        /// Send a request, following up to `max_redirects` redirects. 301, 302 and 303
+        /// are retried as `GET` without a body, 307 and 308 repeat the original request.
+        /// Credentials and cookies in `headers` are only sent to the original server.
+        pub fn request(&self, method: HttpMethod, url: &str, body: &[u8], headers: &HeaderMap) -> Result<HttpResponseMessage, ClientError> {
+            let mut method = method;
+            let mut body = body;
+            let mut headers = headers.clone();
+            let mut url = try!(Url::parse(url));
+
+            let mut redirects = 0;
+            loop {
+                let resp = try!(self.send(&method, &url, body, &headers));
+
+                let code = resp.response_code;
+                let location = resp.headers.get("Location").map(|l| l.clone());
+                if !code.is_redirection() || location.is_none() || code == StatusCode::NotModified {
+                    return Ok(resp);
+                }
+
+                if redirects == self.max_redirects {
+                    return Err(ClientError::TooManyRedirects);
+                }
+                redirects += 1;
+
+                let next = try!(url.join(&location.unwrap()));
+                if follow_redirect(code, &mut method, &mut headers, &url, &next) {
+                    body = &[];
+                }
+                url = next;
+            }
+        }
+
+        fn send(&self, method: &HttpMethod, url: &Url, body: &[u8], headers: &HeaderMap) -> Result<HttpResponseMessage, ClientError> {
+            let mut req = HttpRequestMessage::empty();
+            req.method = method.clone();
+            req.url = url.path.clone();
+            req.http_version = String::from("1.1");
+            req.headers = headers.clone();
+            req.headers.insert("Host", url.host_header());
+            req.headers.insert("Connection", "close");
+            req.body = body.to_vec();
+
+            let mut stream = try!(TcpStream::connect((&url.host[..], url.port)));
+            try!(stream.set_read_timeout(Some(self.timeout)));
+            try!(stream.set_write_timeout(Some(self.timeout)));
+            try!(stream.write_all(&req.to_bytes()));
+
+            let mut parser = HttpResponseParser::for_request(method);
+            let mut buf = [0; 4096];
+            loop {
+                let n = try!(stream.read(&mut buf));
+                let state = if n == 0 { parser.finish() } else { parser.parse_bytes(&buf[..n]) };
+
+                match state {
+                    Ok(HttpResponseParserState::Complete) => break,
+                    Ok(HttpResponseParserState::MoreDataRequired) => (),
+                    Err(e) => return Err(ClientError::Response(e))
+                }
+            }
+
+            Ok(parser.into_response())
+        }
+    }
+
+    /// Adjust method and headers for following a redirect with `code` from `from` to `to`.
+    /// Returns true if the request turned into a `GET` and its body has to be dropped.
+    fn follow_redirect(code: StatusCode, method: &mut HttpMethod, headers: &mut HeaderMap, from: &Url, to: &Url) -> bool {
+        let to_get = code != StatusCode::TemporaryRedirect && code != StatusCode::PermanentRedirect && *method != HttpMethod::Head;
+        if to_get {
+            *method = HttpMethod::Get;
+            for name in ["Content-Type", "Content-Length", "Transfer-Encoding"].iter() {
+                headers.remove(name);
             }
-            Err(_) => { /* connection failed */ }
+        }
+
+        if !to.same_origin(from) {
+            for name in ["Authorization", "Proxy-Authorization", "Cookie"].iter() {
+                headers.remove(name);
+            }
+        }
+
+        to_get
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Url {
+        host: String,
+        port: u16,
+        path: String,
+    }
+
+    impl Url {
+        fn parse(url: &str) -> Result<Url, ClientError> {
+            if !url.starts_with("http://") { return Err(ClientError::InvalidUrl); }
+            // the fragment is never sent to the server
+            let rest = &strip_fragment(url)[7..];
+
+            // the authority ends at the path, or at the query when there's no path
+            let (authority, path) = match rest.find(|c| c == '/' || c == '?') {
+                Some(i) if rest[i..].starts_with("/") => (&rest[..i], rest[i..].to_string()),
+                Some(i) => (&rest[..i], format!("/{}", &rest[i..])),
+                None => (rest, "/".to_string())
+            };
+
+            let (host, port) = match authority.rfind(':') {
+                Some(i) => {
+                    let port = authority[(i + 1)..].parse::<u16>();
+                    if port.is_err() { return Err(ClientError::InvalidUrl); }
+                    (&authority[..i], port.unwrap())
+                },
+                None => (authority, 80)
+            };
+
+            if host.len() == 0 { return Err(ClientError::InvalidUrl); }
+
+            Ok(Url {
+                host: host.to_string(),
+                port: port,
+                path: path
+            })
+        }
+
+        /// Resolve a `Location` header: an absolute URL, a scheme-relative `//host/path`,
+        /// or a path on the same server, relative ones like `next` or `../a` being taken
+        /// from the directory of the current path (RFC 7231, section 7.1.2).
+        fn join(&self, location: &str) -> Result<Url, ClientError> {
+            if has_scheme(location) {
+                return Url::parse(location);
+            }
+            if location.starts_with("//") {
+                return Url::parse(&format!("http:{}", location));
+            }
+
+            let location = strip_fragment(location);
+            let (loc_path, query) = match location.find('?') {
+                Some(i) => (&location[..i], &location[i..]),
+                None => (location, "")
+            };
+            let current = match self.path.find('?') {
+                Some(i) => &self.path[..i],
+                None => &self.path[..]
+            };
+
+            let path = if loc_path.starts_with("/") {
+                remove_dot_segments(loc_path)
+            } else if loc_path.len() == 0 {
+                // only a query, or nothing at all: stay on the current path
+                current.to_string()
+            } else {
+                let dir = match current.rfind('/') {
+                    Some(i) => &current[..(i + 1)],
+                    None => "/"
+                };
+                remove_dot_segments(&format!("{}{}", dir, loc_path))
+            };
+
+            Ok(Url {
+                host: self.host.clone(),
+                port: self.port,
+                path: format!("{}{}", path, query)
+            })
+        }
+
+        fn same_origin(&self, other: &Url) -> bool {
+            self.host.to_lowercase() == other.host.to_lowercase() && self.port == other.port
+        }
+
+        fn host_header(&self) -> String {
+            if self.port == 80 { self.host.clone() } else { format!("{}:{}", self.host, self.port) }
+        }
+    }
+
+    fn strip_fragment(url: &str) -> &str {
+        match url.find('#') {
+            Some(i) => &url[..i],
+            None => url
+        }
+    }
+
+    /// Whether a reference starts with a scheme, like `http:` or `ftp:`.
+    fn has_scheme(url: &str) -> bool {
+        match url.find(':') {
+            Some(i) if i > 0 => {
+                let scheme = &url[..i];
+                scheme.chars().next().unwrap().is_alphabetic() &&
+                    scheme.chars().all(|c| c.is_alphanumeric() || c == '+' || c == '-' || c == '.')
+            },
+            _ => false
+        }
+    }
+
+    /// Resolve the `.` and `..` segments of an absolute path, as in RFC 3986, section 5.2.4.
+    fn remove_dot_segments(path: &str) -> String {
+        let segments: Vec<&str> = path.split('/').skip(1).collect();
+        let mut out: Vec<&str> = Vec::new();
+
+        for (i, segment) in segments.iter().enumerate() {
+            let last = i + 1 == segments.len();
+            match *segment {
+                "." => (),
+                ".." => { out.pop(); },
+                s => { out.push(s); continue; }
+            }
+            // a path ending in a dot segment still names a directory
+            if last { out.push(""); }
+        }
+
+        format!("/{}", out.join("/"))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use super::{Url, follow_redirect};
+        use core_mini_http::*;
+
+        #[test]
+        pub fn test_url_parse() {
+            let u = Url::parse("http://example.com:8080/a/b?x=1").unwrap();
+            assert_eq!(u.host, "example.com");
+            assert_eq!(u.port, 8080);
+            assert_eq!(u.path, "/a/b?x=1");
+            assert_eq!(u.host_header(), "example.com:8080");
+
+            let u = Url::parse("http://example.com").unwrap();
+            assert_eq!(u.port, 80);
+            assert_eq!(u.path, "/");
+            assert_eq!(u.host_header(), "example.com");
+
+            let u = Url::parse("http://example.com?x=1").unwrap();
+            assert_eq!(u.host, "example.com");
+            assert_eq!(u.path, "/?x=1");
+
+            let u = Url::parse("http://example.com:8080#top").unwrap();
+            assert_eq!(u.port, 8080);
+            assert_eq!(u.path, "/");
+
+            for url in ["https://example.com/", "example.com/", "http://:80/", "http://example.com:x/"].iter() {
+                match Url::parse(url) {
+                    Err(ClientError::InvalidUrl) => (),
+                    r => panic!("{} should be invalid, got {:?}", url, r)
+                }
+            }
+        }
+
+        #[test]
+        pub fn test_url_join() {
+            let base = Url::parse("http://example.com:8080/a/b").unwrap();
+
+            let u = base.join("/login?next=a").unwrap();
+            assert_eq!(u, Url { host: String::from("example.com"), port: 8080, path: String::from("/login?next=a") });
+            assert!(u.same_origin(&base));
+
+            let u = base.join("http://other.com/x").unwrap();
+            assert_eq!(u, Url { host: String::from("other.com"), port: 80, path: String::from("/x") });
+            assert!(!u.same_origin(&base));
+
+            let u = base.join("//other.com:81/x").unwrap();
+            assert_eq!(u, Url { host: String::from("other.com"), port: 81, path: String::from("/x") });
+
+            // same host, different port
+            assert!(!base.join("http://example.com/").unwrap().same_origin(&base));
+
+            // relative to the directory of the current path
+            let base = Url::parse("http://example.com/a/b/c?x=1").unwrap();
+            assert_eq!(base.join("next").unwrap().path, "/a/b/next");
+            assert_eq!(base.join("../a").unwrap().path, "/a/a");
+            assert_eq!(base.join("./d?y=2#top").unwrap().path, "/a/b/d?y=2");
+            assert_eq!(base.join("../../../..").unwrap().path, "/");
+            assert_eq!(base.join("..").unwrap().path, "/a/");
+            assert_eq!(base.join("?y=2").unwrap().path, "/a/b/c?y=2");
+            assert_eq!(base.join("/x/./y/../z").unwrap().path, "/x/z");
+            assert_eq!(base.join("next").unwrap().host, "example.com");
+        }
+
+        #[test]
+        pub fn test_follow_redirect() {
+            let from = Url::parse("http://example.com/form").unwrap();
+            let same = from.join("/done").unwrap();
+            let other = from.join("//other.com/done").unwrap();
+
+            let mut headers = HeaderMap::new();
+            headers.insert("Content-Type", "application/x-www-form-urlencoded");
+            headers.insert("Authorization", "Basic YTpi");
+            headers.insert("Accept", "text/html");
+
+            // 303 after a POST fetches the result with GET
+            let mut method = HttpMethod::Post;
+            let mut h = headers.clone();
+            assert!(follow_redirect(StatusCode::SeeOther, &mut method, &mut h, &from, &same));
+            assert_eq!(method, HttpMethod::Get);
+            assert!(!h.contains_key("Content-Type"));
+            assert_eq!(h.get("Authorization").unwrap(), "Basic YTpi");
+
+            // 307 repeats the request, credentials stay with the original server
+            let mut method = HttpMethod::Post;
+            let mut h = headers.clone();
+            assert!(!follow_redirect(StatusCode::TemporaryRedirect, &mut method, &mut h, &from, &other));
+            assert_eq!(method, HttpMethod::Post);
+            assert!(h.contains_key("Content-Type"));
+            assert!(!h.contains_key("Authorization"));
+            assert_eq!(h.get("Accept").unwrap(), "text/html");
+
+            let mut method = HttpMethod::Head;
+            let mut h = headers.clone();
+            assert!(!follow_redirect(StatusCode::Found, &mut method, &mut h, &from, &same));
+            assert_eq!(method, HttpMethod::Head);
         }
     }
 }
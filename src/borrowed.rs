@@ -0,0 +1,194 @@
+use core::str::from_utf8;
+use http::*;
+use parser::*;
+
+/// A header field, `(name, value)`, borrowed from the buffer the request was parsed from.
+pub type HeaderSlot<'b> = (&'b str, &'b [u8]);
+
+/// An unused header slot, for initializing the array handed to `BorrowedRequest::new`.
+pub const EMPTY_HEADER: HeaderSlot<'static> = ("", &[]);
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum BorrowedParseStatus {
+    /// The request head is complete, the value is its length in bytes.
+    /// The body, if any, starts right after it.
+    Complete(usize),
+    Partial,
+}
+
+/// Request head parsed without any allocation.
+///
+/// Method, target and version borrow from the input buffer and the header fields
+/// are written into a caller-provided array of slots, so the number of headers
+/// is bounded by its length. The whole head is parsed again on every call, pass
+/// the full buffer received so far.
+#[derive(Debug)]
+pub struct BorrowedRequest<'h, 'b: 'h> {
+    pub method: Option<&'b str>,
+    pub path: Option<&'b str>,
+    pub version: Option<&'b str>,
+    header_slots: &'h mut [HeaderSlot<'b>],
+    header_count: usize,
+}
+
+impl<'h, 'b> BorrowedRequest<'h, 'b> {
+    pub fn new(headers: &'h mut [HeaderSlot<'b>]) -> BorrowedRequest<'h, 'b> {
+        BorrowedRequest {
+            method: None,
+            path: None,
+            version: None,
+            header_slots: headers,
+            header_count: 0
+        }
+    }
+
+    /// The header fields parsed so far.
+    pub fn headers(&self) -> &[HeaderSlot<'b>] {
+        &self.header_slots[..self.header_count]
+    }
+
+    /// The first value of the header `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&'b [u8]> {
+        for &(k, v) in self.headers() {
+            if eq_ignore_ascii_case(k, name) {
+                return Some(v);
+            }
+        }
+
+        None
+    }
+
+    pub fn parse(&mut self, buf: &'b [u8]) -> Result<BorrowedParseStatus, HttpRequestParserError> {
+        self.header_count = 0;
+
+        let mut pos = 0;
+        let mut first = true;
+        let mut i = 0;
+        while i + 1 < buf.len() {
+            if buf[i] != b'\r' || buf[i + 1] != b'\n' {
+                i += 1;
+                continue;
+            }
+
+            let line = &buf[pos..i];
+            pos = i + 2;
+            i = pos;
+
+            if first {
+                if line.len() == 0 {
+                    // some clients send an extra CRLF after a body, RFC 7230 section 3.5
+                    continue;
+                }
+
+                let str = from_utf8(line);
+                if !str.is_ok() { return Err(HttpRequestParserError::InvalidString); }
+
+                let request_line = try!(parse_request_line(str.unwrap()).map_err(HttpRequestParserError::InvalidRequestLine));
+                self.method = Some(request_line.method);
+                self.path = Some(request_line.target);
+                self.version = Some(request_line.version);
+                first = false;
+                continue;
+            }
+
+            if line.len() == 0 {
+                return Ok(BorrowedParseStatus::Complete(pos));
+            }
+
+            try!(self.parse_header(line));
+        }
+
+        Ok(BorrowedParseStatus::Partial)
+    }
+
+    fn parse_header(&mut self, line: &'b [u8]) -> Result<(), HttpRequestParserError> {
+        let sep = line.iter().position(|&b| b == b':');
+        if sep.is_none() { return Err(HttpRequestParserError::HeaderError); }
+        let sep = sep.unwrap();
+
+        // folded lines can't be joined without copying, and are rejected like whitespace in names
+        let name = &line[..sep];
+        try!(check_header_name(name));
+        let name = from_utf8(name).unwrap();
+
+        // only lines ending in CRLF are split off, a bare CR or LF would stay inside the value
+        let mut value = &line[(sep + 1)..];
+        try!(check_header_value(value));
+        while value.len() > 0 && (value[0] == b' ' || value[0] == b'\t') {
+            value = &value[1..];
+        }
+        while value.len() > 0 && (value[value.len() - 1] == b' ' || value[value.len() - 1] == b'\t') {
+            value = &value[..(value.len() - 1)];
+        }
+
+        if self.header_count == self.header_slots.len() {
+            return Err(HttpRequestParserError::TooManyHeaders);
+        }
+        self.header_slots[self.header_count] = (name, value);
+        self.header_count += 1;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::*;
+
+    #[test]
+    pub fn test_borrowed_parsing() {
+        let msg = b"POST /form?x=1 HTTP/1.1\r\nHost:example.com\r\nContent-Length: 2 \r\n\r\nok";
+
+        let mut headers = [EMPTY_HEADER; 4];
+        let mut req = BorrowedRequest::new(&mut headers);
+
+        assert_eq!(req.parse(&msg[..20]).unwrap(), BorrowedParseStatus::Partial);
+        assert_eq!(req.parse(&msg[..40]).unwrap(), BorrowedParseStatus::Partial);
+        assert_eq!(req.parse(msg).unwrap(), BorrowedParseStatus::Complete(msg.len() - 2));
+
+        assert_eq!(req.method, Some("POST"));
+        assert_eq!(req.path, Some("/form?x=1"));
+        assert_eq!(req.version, Some("1.1"));
+        assert_eq!(req.headers().len(), 2);
+        assert_eq!(req.headers()[0], ("Host", &b"example.com"[..]));
+        assert_eq!(req.header("content-length"), Some(&b"2"[..]));
+
+        let mut headers = [EMPTY_HEADER; 1];
+        let mut req = BorrowedRequest::new(&mut headers);
+        match req.parse(msg) {
+            Err(HttpRequestParserError::TooManyHeaders) => (),
+            r => panic!("expected TooManyHeaders, got {:?}", r)
+        }
+
+        let mut headers = [EMPTY_HEADER; 4];
+        let mut req = BorrowedRequest::new(&mut headers);
+        match req.parse(b"GET / HTTP/1.1\r\nHost : a\r\n\r\n") {
+            Err(HttpRequestParserError::WhitespaceInHeaderName) => (),
+            r => panic!("expected WhitespaceInHeaderName, got {:?}", r)
+        }
+
+        for msg in [&b"GET / HTTP/1.1\r\nX: a\nY: b\r\n\r\n"[..], b"GET / HTTP/1.1\r\nX: a\rY: b\r\n\r\n", b"GET / HTTP/1.1\r\nX: a\x00b\r\n\r\n"].iter() {
+            let mut headers = [EMPTY_HEADER; 4];
+            let mut req = BorrowedRequest::new(&mut headers);
+            match req.parse(msg) {
+                Err(HttpRequestParserError::HeaderError) => (),
+                r => panic!("expected HeaderError, got {:?}", r)
+            }
+        }
+
+        let mut headers = [EMPTY_HEADER; 4];
+        let mut req = BorrowedRequest::new(&mut headers);
+        assert!(req.parse(b"GET / HTTP/1.1\r\nX: a\tb\r\n\r\n").is_ok());
+        assert_eq!(req.header("X"), Some(&b"a\tb"[..]));
+
+        // empty lines before the request-line count towards the head length
+        let msg = b"\r\n\r\nGET / HTTP/1.1\r\n\r\n";
+        let mut headers = [EMPTY_HEADER; 4];
+        let mut req = BorrowedRequest::new(&mut headers);
+        assert_eq!(req.parse(&msg[..2]).unwrap(), BorrowedParseStatus::Partial);
+        assert_eq!(req.parse(msg).unwrap(), BorrowedParseStatus::Complete(msg.len()));
+        assert_eq!(req.method, Some("GET"));
+        assert_eq!(req.path, Some("/"));
+    }
+}
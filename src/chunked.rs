@@ -0,0 +1,368 @@
+use core::cmp::min;
+use core::mem;
+use core::str::from_utf8;
+use http::*;
+use collections::vec::*;
+use headers::HeaderMap;
+use parser::{ParserLimits, LineEndingPolicy, check_header_name, check_header_value};
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum ChunkedDecoderError {
+    InvalidChunkSize,
+    MissingChunkTerminator,
+    InvalidTrailer,
+    /// A chunk-size line, extensions included, longer than `max_request_line`.
+    ChunkSizeLineTooLong,
+    /// More trailer fields than `max_header_count`.
+    TooManyTrailers,
+    /// Trailer lines adding up to more than `max_header_bytes`.
+    TrailersTooLarge,
+    /// A CR not followed by LF, or a bare LF unless the policy allows it.
+    BareLineEnding,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum ChunkedDecoderState {
+    Size,
+    Data(u32),
+    DataEnd,
+    Trailer,
+    Done,
+}
+
+/// Incremental decoder for the `chunked` transfer-coding.
+///
+/// Chunk extensions are ignored, trailer fields are collected and can be
+/// merged into the message headers once the last chunk has been read.
+///
+/// Chunk-size lines are held to `max_request_line` and the trailers to the
+/// header limits, so they can't be used to get around those. Their line
+/// endings follow the same `LineEndingPolicy` as the request head.
+pub struct ChunkedDecoder {
+    state: ChunkedDecoderState,
+    limits: ParserLimits,
+    line_endings: LineEndingPolicy,
+    line: Vec<u8>,
+    trailer_bytes: usize,
+    trailers: HeaderMap,
+}
+
+impl ChunkedDecoder {
+    pub fn new() -> ChunkedDecoder {
+        ChunkedDecoder::with_limits(ParserLimits::default())
+    }
+
+    pub fn with_limits(limits: ParserLimits) -> ChunkedDecoder {
+        ChunkedDecoder {
+            state: ChunkedDecoderState::Size,
+            limits: limits,
+            line_endings: LineEndingPolicy::Strict,
+            line: Vec::new(),
+            trailer_bytes: 0,
+            trailers: HeaderMap::new()
+        }
+    }
+
+    pub fn set_line_ending_policy(&mut self, policy: LineEndingPolicy) {
+        self.line_endings = policy;
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.state == ChunkedDecoderState::Done
+    }
+
+    pub fn trailers(&self) -> &HeaderMap {
+        &self.trailers
+    }
+
+    /// Decode as much of `data` as possible, appending the chunk payloads to `body`.
+    ///
+    /// Returns the number of bytes consumed. Decoding stops after the terminating
+    /// zero-size chunk and its trailers, anything past that is left untouched.
+    pub fn decode(&mut self, data: &[u8], body: &mut Vec<u8>) -> Result<usize, ChunkedDecoderError> {
+        let mut i = 0;
+        while i < data.len() {
+            match self.state {
+                ChunkedDecoderState::Done => break,
+                ChunkedDecoderState::Data(remaining) => {
+                    let n = min(remaining as usize, data.len() - i);
+                    body.push_all(&data[i..(i + n)]);
+                    i += n;
+
+                    let remaining = remaining - n as u32;
+                    if remaining == 0 {
+                        self.state = ChunkedDecoderState::DataEnd;
+                    } else {
+                        self.state = ChunkedDecoderState::Data(remaining);
+                    }
+                },
+                _ => {
+                    let b = data[i];
+                    let after_cr = self.line.ends_with(b"\r");
+                    self.line.push(b);
+                    i += 1;
+
+                    if b == b'\n' {
+                        let ending = if after_cr {
+                            2
+                        } else if self.line_endings == LineEndingPolicy::AllowBareLf {
+                            1
+                        } else {
+                            return Err(ChunkedDecoderError::BareLineEnding);
+                        };
+                        let line = mem::replace(&mut self.line, Vec::new());
+                        try!(self.process_line(&line[..(line.len() - ending)]));
+                    } else if after_cr {
+                        return Err(ChunkedDecoderError::BareLineEnding);
+                    } else {
+                        try!(self.check_line_length());
+                    }
+                }
+            }
+        }
+
+        Ok(i)
+    }
+
+    /// The line in progress must not grow past the limits, even before it's complete.
+    fn check_line_length(&self) -> Result<(), ChunkedDecoderError> {
+        // the line ending is only counted once it's complete
+        let len = if self.line.ends_with(b"\r") { self.line.len() - 1 } else { self.line.len() };
+
+        match self.state {
+            ChunkedDecoderState::Trailer => {
+                if self.trailer_bytes + len > self.limits.max_header_bytes {
+                    return Err(ChunkedDecoderError::TrailersTooLarge);
+                }
+            },
+            _ => {
+                if len > self.limits.max_request_line {
+                    return Err(ChunkedDecoderError::ChunkSizeLineTooLong);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn process_line(&mut self, line: &[u8]) -> Result<(), ChunkedDecoderError> {
+        match self.state {
+            ChunkedDecoderState::Size => {
+                let size = try!(parse_chunk_size(line));
+                if size == 0 {
+                    self.state = ChunkedDecoderState::Trailer;
+                } else {
+                    self.state = ChunkedDecoderState::Data(size);
+                }
+            },
+            ChunkedDecoderState::DataEnd => {
+                if line.len() > 0 {
+                    return Err(ChunkedDecoderError::MissingChunkTerminator);
+                }
+                self.state = ChunkedDecoderState::Size;
+            },
+            ChunkedDecoderState::Trailer => {
+                if line.len() == 0 {
+                    self.state = ChunkedDecoderState::Done;
+                    return Ok(());
+                }
+
+                self.trailer_bytes += line.len() + 2;
+                if self.trailer_bytes > self.limits.max_header_bytes {
+                    return Err(ChunkedDecoderError::TrailersTooLarge);
+                }
+                if self.trailers.len() == self.limits.max_header_count {
+                    return Err(ChunkedDecoderError::TooManyTrailers);
+                }
+
+                let str = from_utf8(line);
+                if !str.is_ok() { return Err(ChunkedDecoderError::InvalidTrailer); }
+                let str = str.unwrap();
+
+                let sep = str.find(":");
+                if sep.is_none() || sep.unwrap() == 0 {
+                    return Err(ChunkedDecoderError::InvalidTrailer);
+                }
+                let sep = sep.unwrap();
+
+                let key = &str[0..sep];
+                let val = str[sep + 1..].trim();
+                if check_header_name(key.as_bytes()).is_err() || check_header_value(val.as_bytes()).is_err() {
+                    return Err(ChunkedDecoderError::InvalidTrailer);
+                }
+                self.trailers.append(key, val);
+            },
+            _ => ()
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse the hex chunk size at the start of a chunk-size line, skipping any chunk extensions.
+fn parse_chunk_size(line: &[u8]) -> Result<u32, ChunkedDecoderError> {
+    let mut size: u32 = 0;
+    let mut digits = 0;
+
+    for &b in line {
+        if b == b';' || b == b' ' || b == b'\t' {
+            break;
+        }
+
+        let d = from_hex(b);
+        if d.is_none() { return Err(ChunkedDecoderError::InvalidChunkSize); }
+
+        let s = size.checked_mul(0x10);
+        if s.is_none() { return Err(ChunkedDecoderError::InvalidChunkSize); }
+        size = s.unwrap() + d.unwrap() as u32;
+        digits += 1;
+    }
+
+    if digits == 0 {
+        return Err(ChunkedDecoderError::InvalidChunkSize);
+    }
+
+    Ok(size)
+}
+
+/// Encoder for the `chunked` transfer-coding.
+///
+/// Lets a response body be written out piece by piece, after the head produced
+/// by `HttpResponseMessage::chunked_head_to_bytes`, without knowing its length up front.
+pub struct ChunkedEncoder;
+impl ChunkedEncoder {
+    /// Append `data` as a single chunk. Empty data is skipped, as a zero-size chunk ends the body.
+    pub fn encode_chunk(data: &[u8], output: &mut Vec<u8>) {
+        if data.len() == 0 { return; }
+
+        output.push_all(format!("{:x}\r\n", data.len()).as_bytes());
+        output.push_all(data);
+        output.push_all(b"\r\n");
+    }
+
+    /// Append the terminating zero-size chunk, followed by the optional trailer fields.
+    pub fn encode_last_chunk(trailers: &HeaderMap, output: &mut Vec<u8>) {
+        output.push_all(b"0\r\n");
+        for (key, val) in trailers {
+            output.push_all(format!("{}: {}\r\n", key, val).as_bytes());
+        }
+        output.push_all(b"\r\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use headers::HeaderMap;
+    use parser::{ParserLimits, LineEndingPolicy};
+    use collections::vec::Vec;
+
+    #[test]
+    pub fn test_chunked_decoding() {
+        let data = b"4\r\nWiki\r\n5;ext=1\r\npedia\r\n0\r\nExpires: never\r\n\r\nGET";
+
+        let mut decoder = ChunkedDecoder::new();
+        let mut body = Vec::new();
+        let consumed = decoder.decode(data, &mut body).unwrap();
+
+        assert!(decoder.is_complete());
+        assert_eq!(consumed, data.len() - 3);
+        assert_eq!(&body[..], b"Wikipedia");
+        assert_eq!(decoder.trailers().get("Expires").unwrap(), "never");
+
+        // the same stream, one byte at a time
+        let mut decoder = ChunkedDecoder::new();
+        let mut body = Vec::new();
+        for b in &data[..(data.len() - 3)] {
+            decoder.decode(&[*b], &mut body).unwrap();
+        }
+        assert!(decoder.is_complete());
+        assert_eq!(&body[..], b"Wikipedia");
+
+        let mut decoder = ChunkedDecoder::new();
+        let mut body = Vec::new();
+        assert_eq!(decoder.decode(b"zz\r\n", &mut body), Err(ChunkedDecoderError::InvalidChunkSize));
+
+        let mut decoder = ChunkedDecoder::new();
+        let mut body = Vec::new();
+        assert_eq!(decoder.decode(b"2\r\nabc\r\n", &mut body), Err(ChunkedDecoderError::MissingChunkTerminator));
+
+        let mut decoder = ChunkedDecoder::new();
+        let mut body = Vec::new();
+        assert_eq!(decoder.decode(b"0\r\nX y: a\r\n\r\n", &mut body), Err(ChunkedDecoderError::InvalidTrailer));
+    }
+
+    #[test]
+    pub fn test_chunked_line_endings() {
+        fn decode(policy: LineEndingPolicy, data: &[u8]) -> Result<Vec<u8>, ChunkedDecoderError> {
+            let mut decoder = ChunkedDecoder::new();
+            decoder.set_line_ending_policy(policy);
+            let mut body = Vec::new();
+            try!(decoder.decode(data, &mut body));
+            assert!(decoder.is_complete());
+            Ok(body)
+        }
+
+        assert_eq!(decode(LineEndingPolicy::Strict, b"1\nx\n0\n\n"), Err(ChunkedDecoderError::BareLineEnding));
+        assert_eq!(&decode(LineEndingPolicy::AllowBareLf, b"1\nx\n0\n\n").unwrap()[..], b"x");
+        assert_eq!(&decode(LineEndingPolicy::AllowBareLf, b"1\r\nx\n0\nA: b\r\n\n").unwrap()[..], b"x");
+
+        // a CR has to be followed by LF either way
+        for policy in vec![LineEndingPolicy::Strict, LineEndingPolicy::AllowBareLf] {
+            assert_eq!(decode(policy, b"0\r\nX: a\rb\r\n\r\n"), Err(ChunkedDecoderError::BareLineEnding));
+            assert_eq!(decode(policy, b"1\rx\r\n0\r\n\r\n"), Err(ChunkedDecoderError::BareLineEnding));
+        }
+    }
+
+    #[test]
+    pub fn test_chunked_limits() {
+        let mut limits = ParserLimits::default();
+        limits.max_request_line = 16;
+        limits.max_header_count = 2;
+        limits.max_header_bytes = 32;
+
+        fn decode(limits: ParserLimits, data: &[u8]) -> Result<usize, ChunkedDecoderError> {
+            let mut decoder = ChunkedDecoder::with_limits(limits);
+            let mut body = Vec::new();
+            decoder.decode(data, &mut body)
+        }
+
+        // a chunk extension can't grow without bounds, terminated or not
+        let mut ext = b"1;".to_vec();
+        ext.push_all(&vec![b'x'; 1000000]);
+        assert_eq!(decode(limits, &ext), Err(ChunkedDecoderError::ChunkSizeLineTooLong));
+        assert_eq!(decode(limits, b"1;ext=0123456789a\r\nx\r\n"), Err(ChunkedDecoderError::ChunkSizeLineTooLong));
+        assert!(decode(limits, b"1;ext=012345678\r\nx\r\n0\r\n\r\n").is_ok());
+
+        assert_eq!(decode(limits, b"0\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n"), Err(ChunkedDecoderError::TooManyTrailers));
+        assert_eq!(decode(limits, b"0\r\nA: 0123456789abc\r\nB: 0123456789abc\r\n\r\n"), Err(ChunkedDecoderError::TrailersTooLarge));
+        assert_eq!(decode(limits, b"0\r\nA: 0123456789012345678901234567890123456789"), Err(ChunkedDecoderError::TrailersTooLarge));
+
+        let mut many = b"0\r\n".to_vec();
+        for _ in 0..10000 {
+            many.push_all(b"X: a\r\n");
+        }
+        assert_eq!(decode(ParserLimits::default(), &many), Err(ChunkedDecoderError::TooManyTrailers));
+    }
+
+    #[test]
+    pub fn test_chunked_encoding() {
+        let mut out = Vec::new();
+        ChunkedEncoder::encode_chunk(b"Wiki", &mut out);
+        ChunkedEncoder::encode_chunk(b"", &mut out);
+        ChunkedEncoder::encode_chunk(b"pedia in chunks", &mut out);
+
+        let mut trailers = HeaderMap::new();
+        trailers.append("Expires", "never");
+        ChunkedEncoder::encode_last_chunk(&trailers, &mut out);
+
+        assert_eq!(&out[..], &b"4\r\nWiki\r\nf\r\npedia in chunks\r\n0\r\nExpires: never\r\n\r\n"[..]);
+
+        let mut decoder = ChunkedDecoder::new();
+        let mut body = Vec::new();
+        decoder.decode(&out, &mut body).unwrap();
+        assert!(decoder.is_complete());
+        assert_eq!(&body[..], b"Wikipedia in chunks");
+    }
+}
@@ -0,0 +1,156 @@
+use core::slice;
+use http::eq_ignore_ascii_case;
+use collections::vec::*;
+use collections::String;
+
+/// Header fields of a request or a response.
+///
+/// Names are looked up case-insensitively, fields keep the order they were
+/// added in and the same name can appear multiple times, as with `Set-Cookie`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> HeaderMap {
+        HeaderMap {
+            entries: Vec::new()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Set `name` to `value`, replacing all of its existing values.
+    pub fn insert<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) {
+        let name = name.into();
+        self.remove(&name);
+        self.entries.push((name, value.into()));
+    }
+
+    /// Add a value for `name`, keeping any existing ones.
+    pub fn append<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) {
+        self.entries.push((name.into(), value.into()));
+    }
+
+    /// The first value of `name`.
+    pub fn get(&self, name: &str) -> Option<&String> {
+        self.entries.iter().find(|&&(ref k, _)| eq_ignore_ascii_case(k, name)).map(|&(_, ref v)| v)
+    }
+
+    /// All values of `name`, in insertion order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> HeaderValues<'a> {
+        HeaderValues {
+            name: name,
+            inner: self.entries.iter()
+        }
+    }
+
+    /// The value of the most recently added field, e.g. to continue a folded header line.
+    pub fn last_value_mut(&mut self) -> Option<&mut String> {
+        self.entries.last_mut().map(|&mut (_, ref mut v)| v)
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Remove every value of `name`, returning the first one.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let pos = self.entries.iter().position(|&(ref k, _)| eq_ignore_ascii_case(k, name));
+        if pos.is_none() { return None; }
+
+        let (_, first) = self.entries.remove(pos.unwrap());
+        self.entries.retain(|&(ref k, _)| !eq_ignore_ascii_case(k, name));
+
+        Some(first)
+    }
+
+    pub fn iter(&self) -> HeaderIter {
+        HeaderIter {
+            inner: self.entries.iter()
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a HeaderMap {
+    type Item = (&'a String, &'a String);
+    type IntoIter = HeaderIter<'a>;
+
+    fn into_iter(self) -> HeaderIter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator over all `(name, value)` fields of a `HeaderMap`.
+pub struct HeaderIter<'a> {
+    inner: slice::Iter<'a, (String, String)>,
+}
+
+impl<'a> Iterator for HeaderIter<'a> {
+    type Item = (&'a String, &'a String);
+
+    fn next(&mut self) -> Option<(&'a String, &'a String)> {
+        self.inner.next().map(|&(ref k, ref v)| (k, v))
+    }
+}
+
+/// Iterator over the values of a single header name.
+pub struct HeaderValues<'a> {
+    name: &'a str,
+    inner: slice::Iter<'a, (String, String)>,
+}
+
+impl<'a> Iterator for HeaderValues<'a> {
+    type Item = &'a String;
+
+    fn next(&mut self) -> Option<&'a String> {
+        loop {
+            match self.inner.next() {
+                Some(&(ref k, ref v)) => {
+                    if eq_ignore_ascii_case(k, self.name) {
+                        return Some(v);
+                    }
+                },
+                None => return None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use collections::vec::Vec;
+
+    #[test]
+    pub fn test_header_map() {
+        let mut h = HeaderMap::new();
+        h.append("Set-Cookie", "a=1");
+        h.append("content-length", "5");
+        h.append("Set-Cookie", "b=2");
+
+        assert_eq!(h.len(), 3);
+        assert_eq!(h.get("Content-Length").unwrap(), "5");
+        assert_eq!(h.get("SET-COOKIE").unwrap(), "a=1");
+        assert_eq!(h.get_all("set-cookie").collect::<Vec<_>>(), vec!["a=1", "b=2"]);
+        assert!(h.get("Host").is_none());
+
+        let names: Vec<&str> = h.iter().map(|(k, _)| &k[..]).collect();
+        assert_eq!(names, vec!["Set-Cookie", "content-length", "Set-Cookie"]);
+
+        h.insert("Content-Length", "7");
+        assert_eq!(h.get_all("content-length").collect::<Vec<_>>(), vec!["7"]);
+
+        assert_eq!(h.remove("set-cookie").unwrap(), "a=1");
+        assert!(!h.contains_key("Set-Cookie"));
+        assert_eq!(h.len(), 1);
+    }
+}
@@ -1,10 +1,16 @@
+use core::cmp::min;
 use core::str::from_utf8;
 use collections::vec::*;
 use collections::String;
 use collections::string::ToString;
 use collections::BTreeMap;
+use headers::HeaderMap;
+use status::StatusCode;
+use date::*;
+use uri::*;
+use chunked::ChunkedEncoder;
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum HttpMethod {
     Get,
     Post,
@@ -12,6 +18,45 @@ pub enum HttpMethod {
     Put,
     Delete,
     Options,
+    Patch,
+    Trace,
+    Connect,
+    /// Any other method token, such as the WebDAV verbs.
+    Extension(String),
+}
+
+impl HttpMethod {
+    /// Map a request-line method token to a method. Tokens are case-sensitive,
+    /// unknown ones become `Extension`.
+    pub fn from_token(token: &str) -> HttpMethod {
+        match token {
+            "GET" => HttpMethod::Get,
+            "POST" => HttpMethod::Post,
+            "HEAD" => HttpMethod::Head,
+            "PUT" => HttpMethod::Put,
+            "DELETE" => HttpMethod::Delete,
+            "OPTIONS" => HttpMethod::Options,
+            "PATCH" => HttpMethod::Patch,
+            "TRACE" => HttpMethod::Trace,
+            "CONNECT" => HttpMethod::Connect,
+            _ => HttpMethod::Extension(token.to_string())
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match *self {
+            HttpMethod::Get => "GET",
+            HttpMethod::Post => "POST",
+            HttpMethod::Head => "HEAD",
+            HttpMethod::Put => "PUT",
+            HttpMethod::Delete => "DELETE",
+            HttpMethod::Options => "OPTIONS",
+            HttpMethod::Patch => "PATCH",
+            HttpMethod::Trace => "TRACE",
+            HttpMethod::Connect => "CONNECT",
+            HttpMethod::Extension(ref m) => m
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -25,8 +70,14 @@ pub struct HttpRequestMessage {
     pub method: HttpMethod,
     pub http_version: String,
     pub url: String,
-    pub headers: BTreeMap<String, String>,
+    /// `url` parsed into path segments, query and fragment. Set by `HttpRequestParser`,
+    /// routes parse `url` themselves when it's missing.
+    pub uri: Option<Uri>,
+    pub headers: HeaderMap,
     pub body: Vec<u8>,
+    /// Trailer fields sent after a chunked body. Kept apart from `headers` as they
+    /// arrive after the body was framed and aren't checked like the headers are.
+    pub trailers: HeaderMap,
 }
 
 impl HttpRequestMessage {
@@ -35,20 +86,72 @@ impl HttpRequestMessage {
             method: HttpMethod::Get,
             http_version: String::new(),
             url: String::new(),
-            headers: BTreeMap::new(),
-            body: Vec::new()
+            uri: None,
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+            trailers: HeaderMap::new()
+        }
+    }
+
+    /// Whether the client wants the connection kept open after the response: the
+    /// default for HTTP/1.1 unless it sent `Connection: close`, opt-in for HTTP/1.0.
+    pub fn keep_alive(&self) -> bool {
+        if self.http_version == "1.0" {
+            self.has_connection_token("keep-alive")
+        } else {
+            !self.has_connection_token("close")
+        }
+    }
+
+    /// Serialize the request, for talking to other servers. An empty version is sent
+    /// as HTTP/1.1. The framing is derived from the body: it's sent in chunks, followed
+    /// by the trailers, when a `Transfer-Encoding` is set, otherwise with its length.
+    /// A `Content-Length` set by the caller is replaced.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+
+        let version = if self.http_version.len() > 0 { &self.http_version[..] } else { "1.1" };
+        output_line(&mut ret, &format!("{} {} HTTP/{}", self.method.as_str(), self.url, version));
+
+        for (key, val) in &self.headers {
+            if eq_ignore_ascii_case(key, "Content-Length") || eq_ignore_ascii_case(key, "Transfer-Encoding") { continue; }
+            output_line(&mut ret, &format!("{}: {}", key, val));
+        }
+
+        if self.headers.contains_key("Transfer-Encoding") {
+            output_line(&mut ret, &chunked_transfer_encoding(&self.headers));
+            output_line(&mut ret, "");
+
+            ChunkedEncoder::encode_chunk(&self.body, &mut ret);
+            ChunkedEncoder::encode_last_chunk(&self.trailers, &mut ret);
+            return ret;
+        }
+
+        // servers may insist on a length for methods that usually have a body, even an empty one
+        let expects_body = match self.method {
+            HttpMethod::Post | HttpMethod::Put | HttpMethod::Patch => true,
+            _ => false
+        };
+        if self.body.len() > 0 || expects_body {
+            output_line(&mut ret, &format!("Content-Length: {}", self.body.len()));
         }
+
+        output_line(&mut ret, "");
+
+        ret.push_all(&self.body);
+
+        ret
     }
 }
 
 impl HttpHeaders for HttpRequestMessage {
-    fn get_raw_headers(&self) -> &BTreeMap<String, String> {
+    fn get_raw_headers(&self) -> &HeaderMap {
         &self.headers
     }
 }
 
 pub trait HttpHeaders {
-    fn get_raw_headers(&self) -> &BTreeMap<String, String>;
+    fn get_raw_headers(&self) -> &HeaderMap;
 
     fn get_raw_header(&self, key: &str) -> Option<&String> {
         let h = self.get_raw_headers();
@@ -77,6 +180,51 @@ pub trait HttpHeaders {
 
         HttpContentType::Unknown
     }
+
+    /// True if the `Connection` header lists `token`, e.g. `close`.
+    fn has_connection_token(&self, token: &str) -> bool {
+        let c = self.get_raw_header("Connection");
+        if c.is_some() {
+            return c.unwrap().split(",").any(|t| eq_ignore_ascii_case(t.trim(), token));
+        }
+
+        false
+    }
+
+    /// True if `chunked` is the final transfer-coding applied to the body,
+    /// taking the codings of all `Transfer-Encoding` fields together.
+    fn is_chunked(&self) -> bool {
+        let mut last = "";
+        for val in self.get_raw_headers().get_all("Transfer-Encoding") {
+            for coding in val.split(",").map(|c| c.trim()) {
+                if coding.len() > 0 {
+                    last = coding;
+                }
+            }
+        }
+
+        eq_ignore_ascii_case(last, "chunked")
+    }
+}
+
+/// `tchar` from RFC 7230, the characters allowed in method names and header field names.
+pub fn is_token_char(b: u8) -> bool {
+    match b {
+        b'0' ... b'9' | b'A' ... b'Z' | b'a' ... b'z' => true,
+        b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => true,
+        _ => false
+    }
+}
+
+/// ASCII case-insensitive string comparison, as used for header names and tokens.
+pub fn eq_ignore_ascii_case(a: &str, b: &str) -> bool {
+    if a.len() != b.len() { return false; }
+
+    fn lower(b: u8) -> u8 {
+        if b >= b'A' && b <= b'Z' { b + 32 } else { b }
+    }
+
+    a.bytes().zip(b.bytes()).all(|(x, y)| lower(x) == lower(y))
 }
 
 pub struct BodyFormParser;
@@ -186,50 +334,519 @@ fn test_form_parser() {
 
 #[derive(Debug)]
 pub struct HttpResponseMessage {
-    pub response_code: u16,
+    pub response_code: StatusCode,
+    /// Reason phrase sent after the code.
     pub response_status: String,
     pub http_version: String,
-    pub headers: BTreeMap<String, String>,
-    pub body: Vec<u8>
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+    /// Trailer fields received after a chunked body, kept apart from `headers` like
+    /// those of a request. Not sent when serializing, see `ChunkedEncoder` for that.
+    pub trailers: HeaderMap,
+}
+
+impl HttpHeaders for HttpResponseMessage {
+    fn get_raw_headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+}
+
+/// Context for serializing a response.
+#[derive(Default)]
+pub struct SerializeOptions<'a> {
+    /// Method of the request being answered, responses to `HEAD` are sent without a body.
+    pub request_method: Option<&'a HttpMethod>,
+    /// Version of the request being answered, e.g. `1.0`. HTTP/1.0 clients don't know
+    /// transfer-codings, they get the plain body with a `Content-Length` instead.
+    pub request_version: Option<&'a str>,
+    /// Adds `Connection: keep-alive` or `Connection: close`, unless the response already has a `Connection` header.
+    pub keep_alive: Option<bool>,
+    /// Adds a `Date` header when the clock knows the time.
+    pub clock: Option<&'a HttpClock>,
+}
+
+/// Result of a `ResponseSerializer::write_to` call.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SerializeProgress {
+    /// Bytes written into the buffer.
+    pub written: usize,
+    /// True if there's more of the response left to write.
+    pub more: bool,
+}
+
+/// Writes a response incrementally into fixed-size buffers, for network stacks
+/// with small transmit buffers that have to be drained piece by piece.
+///
+/// Only the head is rendered up front, the body is copied straight out of the message.
+pub struct ResponseSerializer<'a> {
+    head: Vec<u8>,
+    body: &'a [u8],
+    tail: &'static [u8],
+    pos: usize,
+}
+
+impl<'a> ResponseSerializer<'a> {
+    /// Bytes of the response not yet written.
+    pub fn remaining(&self) -> usize {
+        self.head.len() + self.body.len() + self.tail.len() - self.pos
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// Fill as much of `buf` as possible with the next part of the response.
+    pub fn write_to(&mut self, buf: &mut [u8]) -> SerializeProgress {
+        let mut written = 0;
+
+        while written < buf.len() && !self.is_done() {
+            let (part, offset) = if self.pos < self.head.len() {
+                (&self.head[..], self.pos)
+            } else if self.pos < self.head.len() + self.body.len() {
+                (self.body, self.pos - self.head.len())
+            } else {
+                (self.tail, self.pos - self.head.len() - self.body.len())
+            };
+
+            let n = min(buf.len() - written, part.len() - offset);
+            for i in 0..n {
+                buf[written + i] = part[offset + i];
+            }
+
+            written += n;
+            self.pos += n;
+        }
+
+        SerializeProgress {
+            written: written,
+            more: !self.is_done()
+        }
+    }
+}
+
+/// How the body of a response is delimited on the wire.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum BodyFraming {
+    /// 1xx, 204 and 304 responses never have a body.
+    NoBody,
+    ContentLength,
+    Chunked,
+}
+
+/// The `Transfer-Encoding` line for a body sent in chunks: the codings in `headers`
+/// other than chunked, followed by chunked as the last one.
+fn chunked_transfer_encoding(headers: &HeaderMap) -> String {
+    let mut codings = Vec::new();
+    for val in headers.get_all("Transfer-Encoding") {
+        for coding in val.split(",").map(|c| c.trim()) {
+            if coding.len() > 0 && !eq_ignore_ascii_case(coding, "chunked") {
+                codings.push(coding);
+            }
+        }
+    }
+    codings.push("chunked");
+
+    format!("Transfer-Encoding: {}", codings.join(", "))
+}
+
+fn output_line(r: &mut Vec<u8>, s: &str) {
+    r.push_all(s.as_bytes());
+    r.push_all(b"\r\n");
 }
 
 impl HttpResponseMessage {
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut ret = Vec::new();
+        self.to_bytes_with(&SerializeOptions::default())
+    }
 
-        fn output_line(r: &mut Vec<u8>, s: &str) {
-            let nl = b"\r\n";
-            for b in s.bytes() {
-                r.push(b);
-            }
-            for b in nl {
-                r.push(*b);
+    /// Serialize the response, adding the `Content-Length` (or chunk-encoding the body
+    /// when a `Transfer-Encoding` is set), `Connection` and `Date` headers as needed.
+    /// The length is taken from the body, one set by the caller only stays on a 304
+    /// or a response to `HEAD`.
+    pub fn to_bytes_with(&self, options: &SerializeOptions) -> Vec<u8> {
+        let (mut ret, body, tail) = self.wire_parts(options);
+        ret.push_all(body);
+        ret.push_all(tail);
+
+        ret
+    }
+
+    /// Serialize into caller-supplied buffers instead of one allocation holding the
+    /// whole response, see `ResponseSerializer`.
+    pub fn serializer<'a>(&'a self, options: &SerializeOptions) -> ResponseSerializer<'a> {
+        let (head, body, tail) = self.wire_parts(options);
+
+        ResponseSerializer {
+            head: head,
+            body: body,
+            tail: tail,
+            pos: 0
+        }
+    }
+
+    /// The response as it goes on the wire: the head (plus the chunk-size line
+    /// of a chunked body), the body itself and whatever has to follow it.
+    fn wire_parts(&self, options: &SerializeOptions) -> (Vec<u8>, &[u8], &'static [u8]) {
+        let framing = self.body_framing(options);
+        let mut head = self.write_head(options, framing);
+
+        if !self.sends_body(options) {
+            return (head, &[], b"");
+        }
+
+        if framing == BodyFraming::Chunked {
+            if self.body.len() == 0 {
+                return (head, &[], b"0\r\n\r\n");
             }
+
+            head.push_all(format!("{:x}\r\n", self.body.len()).as_bytes());
+            return (head, &self.body, b"\r\n0\r\n\r\n");
+        }
+
+        (head, &self.body, b"")
+    }
+
+    /// Status line and headers, including the empty line that ends the head.
+    pub fn head_to_bytes(&self) -> Vec<u8> {
+        let options = SerializeOptions::default();
+        self.write_head(&options, self.body_framing(&options))
+    }
+
+    /// Head for a response whose body will follow as `ChunkedEncoder` chunks.
+    /// `body` is ignored and any `Content-Length` header is dropped.
+    pub fn chunked_head_to_bytes(&self) -> Vec<u8> {
+        self.write_head(&SerializeOptions::default(), BodyFraming::Chunked)
+    }
+
+    fn body_framing(&self, options: &SerializeOptions) -> BodyFraming {
+        let code = self.response_code;
+        if code.is_informational() || code == StatusCode::NoContent || code == StatusCode::NotModified {
+            BodyFraming::NoBody
+        } else if self.headers.contains_key("Transfer-Encoding") && options.request_version != Some("1.0") {
+            // any other transfer-coding has to be followed by chunked, RFC 7230 section 3.3.1
+            BodyFraming::Chunked
+        } else {
+            BodyFraming::ContentLength
         }
+    }
+
+    fn sends_body(&self, options: &SerializeOptions) -> bool {
+        self.body_framing(options) != BodyFraming::NoBody && options.request_method != Some(&HttpMethod::Head)
+    }
+
+    fn write_head(&self, options: &SerializeOptions, framing: BodyFraming) -> Vec<u8> {
+        let mut ret = Vec::new();
 
         output_line(&mut ret, &format!("HTTP/{} {} {}", self.http_version, self.response_code, self.response_status));
 
+        // a response to HEAD may announce the length of the body it leaves out
+        let keep_length = options.request_method == Some(&HttpMethod::Head) && self.headers.contains_key("Content-Length");
+
         for (key, val) in &self.headers {
+            let is_length = eq_ignore_ascii_case(key, "Content-Length");
+            let is_encoding = eq_ignore_ascii_case(key, "Transfer-Encoding");
+
+            let skip = match framing {
+                // 304 may repeat the length of the resource, other bodiless responses must not frame anything
+                BodyFraming::NoBody => self.response_code != StatusCode::NotModified && (is_length || is_encoding),
+                BodyFraming::ContentLength => is_encoding || (is_length && !keep_length),
+                BodyFraming::Chunked => is_length || is_encoding
+            };
+            if skip { continue; }
+
             output_line(&mut ret, &format!("{}: {}", key, val));
         }
 
-        output_line(&mut ret, "");
+        if framing == BodyFraming::Chunked {
+            output_line(&mut ret, &chunked_transfer_encoding(&self.headers));
+        }
+        if framing == BodyFraming::ContentLength && !keep_length {
+            output_line(&mut ret, &format!("Content-Length: {}", self.body.len()));
+        }
 
-        ret.push_all(&self.body);
+        if let Some(keep_alive) = options.keep_alive {
+            if !self.headers.contains_key("Connection") {
+                output_line(&mut ret, if keep_alive { "Connection: keep-alive" } else { "Connection: close" });
+            }
+        }
+
+        if let Some(clock) = options.clock {
+            let now = clock.unix_time();
+            if now.is_some() && !self.headers.contains_key("Date") {
+                output_line(&mut ret, &format!("Date: {}", format_http_date(now.unwrap())));
+            }
+        }
+
+        output_line(&mut ret, "");
 
         ret
     }
 
-    pub fn html_utf8(body: &str) -> HttpResponseMessage {
-        let mut headers = BTreeMap::new();
-        headers.insert(String::from("Content-Type"), String::from("text/html; charset=UTF-U8"));
-
+    /// An empty HTTP/1.1 response with the canonical reason phrase for `status`.
+    pub fn new(status: StatusCode) -> HttpResponseMessage {
         HttpResponseMessage {
-            response_code: 200,
-            response_status: String::from("OK"),
+            response_code: status,
+            response_status: String::from(status.canonical_reason().unwrap_or("")),
             http_version: String::from("1.1"),
-            headers: headers,
-            body: body.bytes().collect()
+            headers: HeaderMap::new(),
+            body: Vec::new(),
+            trailers: HeaderMap::new()
+        }
+    }
+
+    pub fn builder() -> HttpResponseBuilder {
+        HttpResponseBuilder::new()
+    }
+
+    pub fn html_utf8(body: &str) -> HttpResponseMessage {
+        HttpResponseBuilder::new().html(body).build()
+    }
+
+    /// A plain text response, the body being the code and reason phrase.
+    fn status_text(status: StatusCode) -> HttpResponseMessage {
+        let reason = status.canonical_reason().unwrap_or("");
+        HttpResponseBuilder::new().status(status).text(&format!("{} {}", status, reason)).build()
+    }
+
+    pub fn not_found() -> HttpResponseMessage {
+        HttpResponseMessage::status_text(StatusCode::NotFound)
+    }
+
+    /// 302 Found, pointing the client to `location`.
+    pub fn redirect(location: &str) -> HttpResponseMessage {
+        HttpResponseBuilder::new().status(StatusCode::Found).header("Location", location).build()
+    }
+
+    pub fn bad_request(msg: &str) -> HttpResponseMessage {
+        HttpResponseBuilder::new().status(StatusCode::BadRequest).text(msg).build()
+    }
+
+    pub fn internal_server_error() -> HttpResponseMessage {
+        HttpResponseMessage::status_text(StatusCode::InternalServerError)
+    }
+}
+
+/// Fluent construction of an `HttpResponseMessage`, starting from an empty `200 OK`.
+///
+/// `build` adds the `Content-Length` header for the body, unless the response
+/// is sent with a `Transfer-Encoding`.
+pub struct HttpResponseBuilder {
+    msg: HttpResponseMessage,
+}
+
+impl HttpResponseBuilder {
+    pub fn new() -> HttpResponseBuilder {
+        HttpResponseBuilder {
+            msg: HttpResponseMessage::new(StatusCode::Ok)
+        }
+    }
+
+    /// Set the status, along with its canonical reason phrase.
+    pub fn status(mut self, status: StatusCode) -> HttpResponseBuilder {
+        self.msg.response_code = status;
+        self.msg.response_status = String::from(status.canonical_reason().unwrap_or(""));
+        self
+    }
+
+    pub fn reason(mut self, reason: &str) -> HttpResponseBuilder {
+        self.msg.response_status = reason.to_string();
+        self
+    }
+
+    /// Add a header, keeping any earlier values with the same name.
+    pub fn header(mut self, name: &str, value: &str) -> HttpResponseBuilder {
+        self.msg.headers.append(name, value);
+        self
+    }
+
+    pub fn content_type(mut self, content_type: &str) -> HttpResponseBuilder {
+        self.msg.headers.insert("Content-Type", content_type);
+        self
+    }
+
+    pub fn body(mut self, body: &[u8]) -> HttpResponseBuilder {
+        self.msg.body = body.to_vec();
+        self
+    }
+
+    pub fn body_str(self, body: &str) -> HttpResponseBuilder {
+        self.body(body.as_bytes())
+    }
+
+    pub fn text(self, body: &str) -> HttpResponseBuilder {
+        self.content_type("text/plain; charset=utf-8").body_str(body)
+    }
+
+    pub fn html(self, body: &str) -> HttpResponseBuilder {
+        self.content_type("text/html; charset=utf-8").body_str(body)
+    }
+
+    /// An already serialized JSON document.
+    pub fn json(self, body: &str) -> HttpResponseBuilder {
+        self.content_type("application/json").body_str(body)
+    }
+
+    pub fn octet_stream(self, body: &[u8]) -> HttpResponseBuilder {
+        self.content_type("application/octet-stream").body(body)
+    }
+
+    pub fn build(self) -> HttpResponseMessage {
+        let mut msg = self.msg;
+        if !msg.headers.contains_key("Transfer-Encoding") {
+            let len = msg.body.len();
+            msg.headers.insert("Content-Length", format!("{}", len));
+        }
+
+        msg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use status::StatusCode;
+    use date::HttpClock;
+
+    #[test]
+    pub fn test_response_constructors() {
+        let r = HttpResponseMessage::not_found();
+        assert_eq!(r.response_code, StatusCode::NotFound);
+        assert!(r.to_bytes().starts_with(b"HTTP/1.1 404 Not Found\r\n"));
+        assert_eq!(&r.body[..], b"404 Not Found");
+
+        let r = HttpResponseMessage::redirect("/login");
+        assert_eq!(r.response_code, StatusCode::Found);
+        assert_eq!(r.headers.get("Location").unwrap(), "/login");
+
+        let r = HttpResponseMessage::bad_request("missing ssid");
+        assert!(r.response_code.is_client_error());
+        assert_eq!(&r.body[..], b"missing ssid");
+
+        let r = HttpResponseMessage::new(StatusCode::Unregistered(299));
+        assert!(r.to_bytes().starts_with(b"HTTP/1.1 299 \r\n"));
+    }
+
+    #[test]
+    pub fn test_response_builder() {
+        let r = HttpResponseMessage::builder()
+            .status(StatusCode::Created)
+            .header("Set-Cookie", "a=1")
+            .header("Set-Cookie", "b=2")
+            .json("{\"id\":1}")
+            .build();
+
+        assert_eq!(r.response_code, StatusCode::Created);
+        assert_eq!(r.response_status, "Created");
+        assert_eq!(r.headers.get("Content-Type").unwrap(), "application/json");
+        assert_eq!(r.headers.get("Content-Length").unwrap(), "8");
+        assert_eq!(r.headers.get_all("Set-Cookie").count(), 2);
+        assert_eq!(&r.body[..], b"{\"id\":1}");
+
+        let r = HttpResponseMessage::builder().status(StatusCode::Ok).reason("Fine").octet_stream(&[1, 2, 3]).build();
+        assert!(r.to_bytes().starts_with(b"HTTP/1.1 200 Fine\r\n"));
+        assert_eq!(r.headers.get("Content-Type").unwrap(), "application/octet-stream");
+
+        let r = HttpResponseMessage::builder().header("Transfer-Encoding", "chunked").text("x").build();
+        assert!(!r.headers.contains_key("Content-Length"));
+
+        let r = HttpResponseMessage::html_utf8("<p>hi</p>");
+        assert_eq!(r.headers.get("Content-Type").unwrap(), "text/html; charset=utf-8");
+        assert_eq!(r.headers.get("Content-Length").unwrap(), "9");
+    }
+
+    #[test]
+    pub fn test_request_keep_alive() {
+        let mut req = HttpRequestMessage::empty();
+        req.http_version = String::from("1.1");
+        assert!(req.keep_alive());
+        req.headers.insert("Connection", "Upgrade, Close");
+        assert!(!req.keep_alive());
+
+        req.http_version = String::from("1.0");
+        assert!(!req.keep_alive());
+        req.headers.insert("Connection", "Keep-Alive");
+        assert!(req.keep_alive());
+    }
+
+    #[test]
+    pub fn test_response_framing() {
+        struct FixedClock;
+        impl HttpClock for FixedClock {
+            fn unix_time(&self) -> Option<u64> { Some(784111777) }
+        }
+
+        let mut r = HttpResponseMessage::new(StatusCode::Ok);
+        r.body = b"hello".to_vec();
+        assert_eq!(&r.to_bytes()[..], &b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello"[..]);
+
+        let head = HttpMethod::Head;
+        let clock = FixedClock;
+        let options = SerializeOptions {
+            request_method: Some(&head),
+            request_version: None,
+            keep_alive: Some(false),
+            clock: Some(&clock)
+        };
+        assert_eq!(&r.to_bytes_with(&options)[..],
+                   &b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\nDate: Sun, 06 Nov 1994 08:49:37 GMT\r\n\r\n"[..]);
+
+        let r = HttpResponseMessage::builder().status(StatusCode::NoContent).body_str("ignored").build();
+        assert_eq!(&r.to_bytes()[..], &b"HTTP/1.1 204 No Content\r\n\r\n"[..]);
+
+        let mut r = HttpResponseMessage::new(StatusCode::NotModified);
+        r.headers.insert("Content-Length", "42");
+        assert_eq!(&r.to_bytes()[..], &b"HTTP/1.1 304 Not Modified\r\nContent-Length: 42\r\n\r\n"[..]);
+
+        let r = HttpResponseMessage::builder().header("Transfer-Encoding", "chunked").body_str("Wiki").build();
+        assert_eq!(&r.to_bytes()[..], &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWiki\r\n0\r\n\r\n"[..]);
+
+        // the length always comes from the body, other transfer-codings are followed by chunked
+        let mut r = HttpResponseMessage::builder().body_str("Wiki").build();
+        r.headers.insert("Content-Length", "42");
+        assert_eq!(&r.to_bytes()[..], &b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nWiki"[..]);
+        let options = SerializeOptions { request_method: Some(&head), ..SerializeOptions::default() };
+        assert_eq!(&r.to_bytes_with(&options)[..], &b"HTTP/1.1 200 OK\r\nContent-Length: 42\r\n\r\n"[..]);
+
+        let r = HttpResponseMessage::builder().header("Transfer-Encoding", "gzip").body_str("Wiki").build();
+        assert_eq!(&r.to_bytes()[..], &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, chunked\r\n\r\n4\r\nWiki\r\n0\r\n\r\n"[..]);
+
+        // HTTP/1.0 clients can't read chunks
+        let r = HttpResponseMessage::builder().header("Transfer-Encoding", "chunked").body_str("Wiki").build();
+        let options = SerializeOptions { request_version: Some("1.0"), ..SerializeOptions::default() };
+        assert_eq!(&r.to_bytes_with(&options)[..], &b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nWiki"[..]);
+
+        let r = HttpResponseMessage::builder().header("Connection", "close").build();
+        let options = SerializeOptions { keep_alive: Some(true), ..SerializeOptions::default() };
+        assert_eq!(&r.to_bytes_with(&options)[..], &b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"[..]);
+    }
+
+    #[test]
+    pub fn test_response_serializer() {
+        let plain = HttpResponseMessage::builder().text("0123456789abcdefghij").build();
+        let chunked = HttpResponseMessage::builder().header("Transfer-Encoding", "chunked").text("0123456789abcdefghij").build();
+        let empty = HttpResponseMessage::builder().header("Transfer-Encoding", "chunked").build();
+
+        for r in [plain, chunked, empty].iter() {
+            let expected = r.to_bytes();
+
+            for size in 1..20 {
+                let mut serializer = r.serializer(&SerializeOptions::default());
+                assert_eq!(serializer.remaining(), expected.len());
+
+                let mut out = Vec::new();
+                let mut buf = vec![0; size];
+                loop {
+                    let p = serializer.write_to(&mut buf);
+                    assert!(p.written <= size);
+                    out.push_all(&buf[..p.written]);
+                    if !p.more { break; }
+                    assert_eq!(p.written, size);
+                }
+
+                assert_eq!(out, expected);
+                assert!(serializer.is_done());
+                assert_eq!(serializer.write_to(&mut buf), SerializeProgress { written: 0, more: false });
+            }
         }
     }
 }
@@ -10,15 +10,36 @@ extern crate alloc;
 #[macro_use]
 extern crate collections;
 
-// for tests
-#[cfg(test)]
+// for tests and the server
+#[cfg(any(test, feature = "std"))]
 #[macro_use]
 extern crate std;
 
 mod http;
+mod headers;
+mod status;
+mod date;
 mod router;
 mod parser;
+mod response_parser;
+mod chunked;
+mod scanner;
+mod borrowed;
+mod transport;
+mod uri;
+#[cfg(feature = "std")]
+mod server;
 
 pub use http::*;
+pub use headers::*;
+pub use status::*;
+pub use date::*;
 pub use router::*;
 pub use parser::*;
+pub use response_parser::*;
+pub use chunked::*;
+pub use borrowed::*;
+pub use transport::*;
+pub use uri::*;
+#[cfg(feature = "std")]
+pub use server::*;
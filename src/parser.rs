@@ -1,14 +1,26 @@
+use core::cmp::min;
+use core::mem;
 use core::str::from_utf8;
 use http::*;
+use chunked::*;
+use scanner::*;
+use headers::HeaderMap;
+use uri::Uri;
+use status::StatusCode;
 use collections::vec::*;
 use collections::String;
 use collections::string::ToString;
 
 pub struct HttpRequestParser {
-    buffer: Vec<u8>,
-    pos: usize,
+    limits: ParserLimits,
+    header_bytes: usize,
+    scanner: LineScanner,
     line_num: u16,
     headers_parsed: bool,
+    chunked: Option<ChunkedDecoder>,
+    obs_fold: ObsFoldPolicy,
+    complete: bool,
+    surplus: Vec<u8>,
     msg: HttpRequestMessage,
 }
 
@@ -22,20 +34,280 @@ pub enum HttpRequestParserState {
 pub enum HttpRequestParserError {
     InvalidString,
     HeaderError,
-    LineParseError(String),
+    InvalidRequestLine(RequestLineError),
+    RequestLineTooLong,
+    TooManyHeaders,
+    HeadersTooLarge,
+    PayloadTooLarge,
+    ChunkedEncodingError(ChunkedDecoderError),
+    /// A CR not followed by LF, or a LF without a CR when those aren't allowed.
+    BareLineEnding,
+    /// `Content-Length` given more than once with different values.
+    ConflictingContentLength,
+    /// Both `Content-Length` and `Transfer-Encoding`, which frame the body differently.
+    ContentLengthWithTransferEncoding,
+    /// A `Content-Length` that isn't a plain decimal number, e.g. signed or with a fraction.
+    InvalidContentLength,
+    /// A `Transfer-Encoding` that doesn't end in `chunked`, so the end of the body is unknown.
+    UnsupportedTransferEncoding,
+    /// Whitespace in a header name or before the colon, RFC 7230 section 3.2.4.
+    WhitespaceInHeaderName,
+}
+
+impl HttpRequestParserError {
+    /// The status to respond with when a request can't be parsed.
+    pub fn status_code(&self) -> StatusCode {
+        match *self {
+            HttpRequestParserError::RequestLineTooLong => StatusCode::UriTooLong,
+            HttpRequestParserError::TooManyHeaders | HttpRequestParserError::HeadersTooLarge => StatusCode::RequestHeaderFieldsTooLarge,
+            HttpRequestParserError::ChunkedEncodingError(ChunkedDecoderError::TooManyTrailers) |
+            HttpRequestParserError::ChunkedEncodingError(ChunkedDecoderError::TrailersTooLarge) => StatusCode::RequestHeaderFieldsTooLarge,
+            HttpRequestParserError::PayloadTooLarge => StatusCode::PayloadTooLarge,
+            _ => StatusCode::BadRequest
+        }
+    }
+}
+
+/// Which part of a request-line was malformed.
+#[derive(Debug, Eq, PartialEq)]
+pub enum RequestLineError {
+    InvalidMethod,
+    InvalidTarget,
+    InvalidVersion,
+}
+
+/// The parts of a request-line, borrowed from the line they were parsed from.
+#[derive(Debug, Eq, PartialEq)]
+pub struct RequestLine<'a> {
+    pub method: &'a str,
+    pub target: &'a str,
+    /// Version number without the `HTTP/` prefix, e.g. `1.1`.
+    pub version: &'a str,
+}
+
+/// Split a request-line into method, request-target and HTTP-version, as in RFC 7230 section 3.1.1:
+/// `method SP request-target SP HTTP-version`, separated by exactly one space each.
+pub fn parse_request_line<'a>(line: &'a str) -> Result<RequestLine<'a>, RequestLineError> {
+    let sp = line.find(" ");
+    let method = if sp.is_some() { &line[..sp.unwrap()] } else { line };
+    if method.len() == 0 || !method.bytes().all(is_token_char) {
+        return Err(RequestLineError::InvalidMethod);
+    }
+    if sp.is_none() {
+        return Err(RequestLineError::InvalidTarget);
+    }
+
+    let rest = &line[(sp.unwrap() + 1)..];
+    let sp = rest.find(" ");
+    let target = if sp.is_some() { &rest[..sp.unwrap()] } else { rest };
+    if target.len() == 0 || !target.bytes().all(|b| b > b' ' && b < 0x7f) {
+        return Err(RequestLineError::InvalidTarget);
+    }
+    if sp.is_none() {
+        return Err(RequestLineError::InvalidVersion);
+    }
+
+    let version = &rest[(sp.unwrap() + 1)..];
+    let v = version.as_bytes();
+    if v.len() != 8 || !version.starts_with("HTTP/") || !is_digit(v[5]) || v[6] != b'.' || !is_digit(v[7]) {
+        return Err(RequestLineError::InvalidVersion);
+    }
+
+    Ok(RequestLine {
+        method: method,
+        target: target,
+        version: &version[5..]
+    })
+}
+
+/// How to treat obsolete line folding, a header line starting with whitespace
+/// that continues the value of the previous header.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ObsFoldPolicy {
+    /// Fail with `HeaderError`, as RFC 7230 section 3.2.4 recommends for servers.
+    Reject,
+    /// Replace the fold with a single space and join the line to the previous value.
+    Unfold,
+}
+
+/// Which line terminators end the request-line, the header lines and the lines of a chunked body.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LineEndingPolicy {
+    /// Only CRLF, a bare CR or LF fails with `BareLineEnding`. Intermediaries
+    /// that split lines differently can't be used to smuggle requests past us.
+    Strict,
+    /// Also accept a bare LF, as sent by hand-written clients and some embedded
+    /// stacks. A bare CR is still rejected, RFC 7230 section 3.5.
+    AllowBareLf,
+}
+
+impl Default for LineEndingPolicy {
+    fn default() -> LineEndingPolicy {
+        LineEndingPolicy::Strict
+    }
+}
+
+#[inline]
+fn is_ows(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+/// Parse a `field-name ":" OWS field-value OWS` header line into `headers`.
+///
+/// Whitespace between the field name and the colon is rejected, surrounding
+/// whitespace is trimmed from the value.
+pub fn parse_header_line(headers: &mut HeaderMap, line: &str, obs_fold: ObsFoldPolicy) -> Result<(), HttpRequestParserError> {
+    if line.starts_with(is_ows) {
+        if obs_fold == ObsFoldPolicy::Reject {
+            return Err(HttpRequestParserError::HeaderError);
+        }
+
+        let last = headers.last_value_mut();
+        if last.is_none() {
+            return Err(HttpRequestParserError::HeaderError);
+        }
+        let last = last.unwrap();
+
+        let val = line.trim_matches(is_ows);
+        try!(check_header_value(val.as_bytes()));
+        if val.len() > 0 {
+            if last.len() > 0 { last.push(' '); }
+            last.push_str(val);
+        }
+
+        return Ok(());
+    }
+
+    let sep = line.find(":");
+    if sep.is_none() {
+        return Err(HttpRequestParserError::HeaderError);
+    }
+    let sep = sep.unwrap();
+
+    let key = &line[0..sep];
+    try!(check_header_name(key.as_bytes()));
+
+    let val = line[sep + 1..].trim_matches(is_ows);
+    try!(check_header_value(val.as_bytes()));
+    headers.append(key, val);
+
+    Ok(())
+}
+
+/// Header field names have to be tokens. Whitespace gets an error of its own, a proxy
+/// might trim it and see a different header than we do, RFC 7230 section 3.2.4.
+pub fn check_header_name(name: &[u8]) -> Result<(), HttpRequestParserError> {
+    if name.iter().any(|&b| b == b' ' || b == b'\t') {
+        return Err(HttpRequestParserError::WhitespaceInHeaderName);
+    }
+    if name.len() == 0 || !name.iter().all(|&b| is_token_char(b)) {
+        return Err(HttpRequestParserError::HeaderError);
+    }
+
+    Ok(())
+}
+
+/// Header field values can't hold control characters other than tab. A CR or LF
+/// that didn't end the line could be taken as one by the next hop.
+pub fn check_header_value(value: &[u8]) -> Result<(), HttpRequestParserError> {
+    if value.iter().any(|&b| (b < 0x20 && b != b'\t') || b == 0x7f) {
+        return Err(HttpRequestParserError::HeaderError);
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn is_digit(b: u8) -> bool {
+    b >= b'0' && b <= b'9'
+}
+
+/// Check the `Content-Length` and `Transfer-Encoding` headers of a request or response:
+/// every length has to be a valid number, all of them the same, and they can't come
+/// together with a transfer-coding. Repeated identical lengths are collapsed into one,
+/// RFC 7230 section 3.3.2. Returns the length, if there is one.
+pub fn check_body_framing(headers: &mut HeaderMap) -> Result<Option<u32>, HttpRequestParserError> {
+    let mut length = None;
+    for val in headers.get_all("Content-Length") {
+        for v in val.split(",") {
+            let v = v.trim_matches(is_ows);
+            if v.len() == 0 || !v.bytes().all(is_digit) {
+                return Err(HttpRequestParserError::InvalidContentLength);
+            }
+            let v = v.parse::<u32>();
+            if v.is_err() {
+                return Err(HttpRequestParserError::InvalidContentLength);
+            }
+            let v = v.unwrap();
+
+            if length.is_some() && length != Some(v) {
+                return Err(HttpRequestParserError::ConflictingContentLength);
+            }
+            length = Some(v);
+        }
+    }
+
+    if length.is_some() && headers.contains_key("Transfer-Encoding") {
+        return Err(HttpRequestParserError::ContentLengthWithTransferEncoding);
+    }
+
+    if let Some(length) = length {
+        headers.insert("Content-Length", length.to_string());
+    }
+
+    Ok(length)
+}
+
+/// Upper bounds on the parts of a request, so a client can't exhaust the memory
+/// of a small device by sending an endless request line, headers or body.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ParserLimits {
+    pub max_request_line: usize,
+    pub max_header_count: usize,
+    /// Total size of the header lines, including their line endings.
+    pub max_header_bytes: usize,
+    pub max_body_size: usize,
+}
+
+impl Default for ParserLimits {
+    fn default() -> ParserLimits {
+        ParserLimits {
+            max_request_line: 2048,
+            max_header_count: 32,
+            max_header_bytes: 8192,
+            max_body_size: 64 * 1024
+        }
+    }
 }
 
 impl HttpRequestParser {
     pub fn new() -> HttpRequestParser {
+        HttpRequestParser::with_limits(ParserLimits::default())
+    }
+
+    pub fn with_limits(limits: ParserLimits) -> HttpRequestParser {
         HttpRequestParser {
-            buffer: Vec::new(),
-            pos: 0,
+            limits: limits,
+            header_bytes: 0,
+            scanner: LineScanner::new(),
             line_num: 0,
             headers_parsed: false,
+            chunked: None,
+            obs_fold: ObsFoldPolicy::Reject,
+            complete: false,
+            surplus: Vec::new(),
             msg: HttpRequestMessage::empty()
         }
     }
 
+    pub fn set_obs_fold_policy(&mut self, policy: ObsFoldPolicy) {
+        self.obs_fold = policy;
+    }
+
+    pub fn set_line_ending_policy(&mut self, policy: LineEndingPolicy) {
+        self.scanner.line_endings = policy;
+    }
+
     pub fn is_first_line_parsed(&self) -> bool {
         self.line_num > 0
     }
@@ -44,124 +316,215 @@ impl HttpRequestParser {
         self.headers_parsed
     }
 
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
     pub fn read_how_many_bytes(&self) -> u32 {
-        if self.is_first_line_parsed() && self.are_headers_parsed() {
-            if self.get_request().method == HttpMethod::Get || self.get_request().method == HttpMethod::Head {
-                return 0;
-            }
+        if self.complete {
+            return 0;
+        }
 
-            let cl = self.msg.content_length();
-            if cl.is_some() {
-                return cl.unwrap() - self.msg.body.len() as u32;
-            }
+        if self.is_first_line_parsed() && self.are_headers_parsed() && self.chunked.is_none() {
+            return self.body_length() - self.msg.body.len() as u32;
         }
 
         return 1;
     }
 
+    /// Bytes received past the end of the complete request, belonging to the next
+    /// request on the same connection.
+    pub fn surplus(&self) -> &[u8] {
+        &self.surplus
+    }
+
+    pub fn take_surplus(&mut self) -> Vec<u8> {
+        mem::replace(&mut self.surplus, Vec::new())
+    }
+
+    /// Get ready for the next request on the same connection. Limits and policies
+    /// are kept, as are the surplus bytes, which are parsed by the next `parse_bytes`
+    /// call, even one with no new data.
+    pub fn reset(&mut self) {
+        self.scanner.reset();
+        self.line_num = 0;
+        self.header_bytes = 0;
+        self.headers_parsed = false;
+        self.chunked = None;
+        self.complete = false;
+        self.msg = HttpRequestMessage::empty();
+    }
+
+    /// Hand over the complete request and `reset` for the next one.
+    pub fn take_request(&mut self) -> Option<HttpRequestMessage> {
+        if !self.complete {
+            return None;
+        }
+
+        let msg = mem::replace(&mut self.msg, HttpRequestMessage::empty());
+        self.reset();
+        Some(msg)
+    }
+
+    /// Length of a non-chunked body. Without a `Content-Length` header, a request has no body.
+    fn body_length(&self) -> u32 {
+        self.msg.content_length().unwrap_or(0)
+    }
+
     pub fn parse_bytes(&mut self, data: &[u8]) -> Result<HttpRequestParserState, HttpRequestParserError> {
-        if data.len() == 0 { return Ok(HttpRequestParserState::MoreDataRequired); }
-
-        self.buffer.push_all(data);
-
-        if self.headers_parsed == false {
-            let p = self.pos;
-            for i in p..self.buffer.len(){
-                //println!("i = {}", i);
-                let f = self.buffer[i];
-                if f == '\r' as u8 {
-                    if i + 1 < self.buffer.len() {
-                        let f2 = self.buffer[i + 1];
-                        if f2 == '\n' as u8 {
-                            // line found
-                            let line = &self.buffer[self.pos..i];
-                            //println!("line: [{}..{}]", self.pos, i);
-
-                            self.pos = i + 2;							
-
-                            if line.len() == 0 {
-                                self.line_num += 1;
-                                self.headers_parsed = true;
-                                break;
-                            }
-
-                            if self.line_num == 0 {
-                                try!(HttpRequestParser::parse_first_line(&mut self.msg, line));
-                            } else {
-                                try!(HttpRequestParser::parse_line(&mut self.msg, line));
-                            }
-
-                            self.line_num += 1;
-                        }
-                    }
-                }
+        if self.complete {
+            self.surplus.push_all(data);
+            return Ok(HttpRequestParserState::Complete);
+        }
+
+        if self.surplus.len() > 0 {
+            // what was left over from the previous request on the connection comes first
+            let mut pending = self.take_surplus();
+            pending.push_all(data);
+            return self.parse_bytes(&pending);
+        }
+
+        let mut i = 0;
+        while i < data.len() && !self.headers_parsed {
+            match self.scanner.scan(&data[i..]) {
+                ScanResult::Line(n) => {
+                    i += n;
+                    try!(self.end_of_line());
+                },
+                ScanResult::Partial => i = data.len(),
+                ScanResult::BareLineEnding => return Err(HttpRequestParserError::BareLineEnding)
+            }
+
+            // the line in progress must not grow past the limits either
+            if self.line_num == 0 && self.scanner.line.len() > self.limits.max_request_line {
+                return Err(HttpRequestParserError::RequestLineTooLong);
+            }
+            if self.line_num > 0 && self.header_bytes + self.scanner.line.len() > self.limits.max_header_bytes {
+                return Err(HttpRequestParserError::HeadersTooLarge);
             }
         }
 
         if self.headers_parsed {
-            {
-                let s = &self.buffer[(self.pos)..];
-                self.msg.body.push_all(s);
+            let data = &data[i..];
+            if self.complete {
+                self.surplus.push_all(data);
+            } else {
+                let c = try!(self.parse_body(data));
+                self.surplus.push_all(&data[c..]);
+            }
+
+            if self.complete {
+                if let Some(ref d) = self.chunked {
+                    self.msg.trailers = d.trailers().clone();
+                }
+                return Ok(HttpRequestParserState::Complete);
             }
-            self.buffer.clear();
-            self.pos = 0;
         }
 
 
         Ok(HttpRequestParserState::MoreDataRequired)
     }
 
-    fn parse_first_line(msg: &mut HttpRequestMessage, line: &[u8]) -> Result<(), HttpRequestParserError> {
-        let str = from_utf8(line);
-        if !str.is_ok() { return Err(HttpRequestParserError::InvalidString); }
-        let str = str.unwrap();
+    /// The line in the scanner is complete, parse it and make room for the next one.
+    fn end_of_line(&mut self) -> Result<(), HttpRequestParserError> {
+        if self.scanner.line.len() == 0 && self.line_num == 0 {
+            // some clients send an extra CRLF after a body, RFC 7230 section 3.5
+            return Ok(());
+        }
+        if self.scanner.line.len() == 0 {
+            self.line_num += 1;
+            self.headers_parsed = true;
+            try!(self.check_framing());
+            // after the check, any transfer-coding ends in chunked
+            if self.msg.headers.contains_key("Transfer-Encoding") {
+                let mut decoder = ChunkedDecoder::with_limits(self.limits);
+                decoder.set_line_ending_policy(self.scanner.line_endings);
+                self.chunked = Some(decoder);
+            } else if self.body_length() as usize > self.limits.max_body_size {
+                // the body grows as it arrives, a client announcing a length doesn't get it allocated up front
+                return Err(HttpRequestParserError::PayloadTooLarge);
+            }
+            return Ok(());
+        }
 
-        let middle;
-
-        if str.starts_with("GET") {
-            msg.method = HttpMethod::Get;
-            middle = &str[4..];
-        } else if str.starts_with("HEAD") {
-            msg.method = HttpMethod::Head;
-            middle = &str[5..];
-        } else if str.starts_with("POST") {
-            msg.method = HttpMethod::Post;
-            middle = &str[5..];
+        if self.line_num == 0 {
+            if self.scanner.line.len() > self.limits.max_request_line {
+                return Err(HttpRequestParserError::RequestLineTooLong);
+            }
+            try!(HttpRequestParser::parse_first_line(&mut self.msg, &self.scanner.line));
         } else {
-            return Err(HttpRequestParserError::LineParseError(str.to_string()));
+            self.header_bytes += self.scanner.line.len() + 2;
+            if self.header_bytes > self.limits.max_header_bytes {
+                return Err(HttpRequestParserError::HeadersTooLarge);
+            }
+            try!(HttpRequestParser::parse_line(&mut self.msg, &self.scanner.line, self.obs_fold));
+            if self.msg.headers.len() > self.limits.max_header_count {
+                return Err(HttpRequestParserError::TooManyHeaders);
+            }
         }
 
-        if str.ends_with("HTTP/1.1") {
-            msg.http_version = String::from("1.1");
-        } else if str.ends_with("HTTP/1.0") {
-            msg.http_version = String::from("1.0");
+        self.line_num += 1;
+        self.scanner.line.clear();
+        Ok(())
+    }
+
+    /// Reject headers that frame the body ambiguously, as a proxy in front of us might
+    /// pick a different end of the body and let a second request be smuggled in it.
+    fn check_framing(&mut self) -> Result<(), HttpRequestParserError> {
+        try!(check_body_framing(&mut self.msg.headers));
+
+        if self.msg.headers.contains_key("Transfer-Encoding") && !self.msg.is_chunked() {
+            // without chunked last, only closing the connection would end the body
+            return Err(HttpRequestParserError::UnsupportedTransferEncoding);
         }
 
-        let l = middle.rfind("HTTP/1");
-        if l.is_none() { return Err(HttpRequestParserError::LineParseError(str.to_string())); }
+        Ok(())
+    }
 
-        let url = &middle[..(l.unwrap() - 1)];
-        msg.url = url.to_string();
+    /// Feed body bytes to the decoder or straight into the body, returns how many belong to this request.
+    fn parse_body(&mut self, s: &[u8]) -> Result<usize, HttpRequestParserError> {
+        let body_length = self.body_length() as usize;
+        let consumed = match self.chunked {
+            Some(ref mut d) => {
+                let c = try!(d.decode(s, &mut self.msg.body).map_err(HttpRequestParserError::ChunkedEncodingError));
+                if self.msg.body.len() > self.limits.max_body_size {
+                    return Err(HttpRequestParserError::PayloadTooLarge);
+                }
+                self.complete = d.is_complete();
+                c
+            },
+            None => {
+                let remaining = body_length.saturating_sub(self.msg.body.len());
+                let c = min(remaining, s.len());
+                self.msg.body.push_all(&s[..c]);
+                self.complete = c == remaining;
+                c
+            }
+        };
 
-        return Ok(());
+        Ok(consumed)
     }
 
-    fn parse_line(msg: &mut HttpRequestMessage, line: &[u8]) -> Result<(), HttpRequestParserError> {
+    fn parse_first_line(msg: &mut HttpRequestMessage, line: &[u8]) -> Result<(), HttpRequestParserError> {
         let str = from_utf8(line);
-        if str.is_ok() {
-            let str = str.unwrap();
+        if !str.is_ok() { return Err(HttpRequestParserError::InvalidString); }
+        let str = str.unwrap();
 
-            let sep = str.find(": ");
-            if sep.is_none() {
-                return Err(HttpRequestParserError::HeaderError);
-            }
-            let sep = sep.unwrap();
+        let request_line = try!(parse_request_line(str).map_err(HttpRequestParserError::InvalidRequestLine));
+        let uri = try!(Uri::parse(request_line.target).map_err(|_| HttpRequestParserError::InvalidRequestLine(RequestLineError::InvalidTarget)));
 
-            let key = &str[0..sep];
-            let val = &str[sep + 2..];
+        msg.method = HttpMethod::from_token(request_line.method);
+        msg.url = request_line.target.to_string();
+        msg.uri = Some(uri);
+        msg.http_version = request_line.version.to_string();
 
-            msg.headers.insert(key.to_string(), val.to_string());
+        return Ok(());
+    }
 
+    fn parse_line(msg: &mut HttpRequestMessage, line: &[u8], obs_fold: ObsFoldPolicy) -> Result<(), HttpRequestParserError> {
+        let str = from_utf8(line);
+        if str.is_ok() {
+            try!(parse_header_line(&mut msg.headers, str.unwrap(), obs_fold));
         } else {
             msg.http_version = String::from("fail");
             return Err(HttpRequestParserError::InvalidString);
@@ -178,8 +541,12 @@ impl HttpRequestParser {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use http::*;
+    use headers::HeaderMap;
+    use scanner::ScanState;
 
     use collections::vec::Vec;
+    use collections::String;
 
     #[test]
     pub fn test_request_parsing() {
@@ -198,4 +565,441 @@ mod tests {
         let req = parser.get_request();
         println!("parsed: {:?}", req);
     }
+
+    #[test]
+    pub fn test_request_completion() {
+        let mut parser = HttpRequestParser::new();
+        match parser.parse_bytes(b"GET / HTTP/1.1\r\nHost: a\r\n\r\nGET /next HTTP/1.1\r\n").unwrap() {
+            HttpRequestParserState::Complete => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+        assert_eq!(parser.read_how_many_bytes(), 0);
+        assert_eq!(parser.get_request().body.len(), 0);
+        assert_eq!(parser.surplus(), b"GET /next HTTP/1.1\r\n");
+
+        let msg = b"POST /form HTTP/1.1\r\nContent-Length: 5\r\n\r\nab";
+        let mut parser = HttpRequestParser::new();
+        match parser.parse_bytes(msg).unwrap() {
+            HttpRequestParserState::MoreDataRequired => (),
+            s => panic!("expected more data, got {:?}", s)
+        }
+        assert_eq!(parser.read_how_many_bytes(), 3);
+        match parser.parse_bytes(b"cdeGET").unwrap() {
+            HttpRequestParserState::Complete => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+        assert_eq!(&parser.get_request().body[..], b"abcde");
+        assert_eq!(&parser.take_surplus()[..], b"GET");
+        assert_eq!(parser.surplus().len(), 0);
+    }
+
+    #[test]
+    pub fn test_pipelined_requests() {
+        let msg = b"POST /a HTTP/1.1\r\nContent-Length: 3\r\n\r\nabcGET /b HTTP/1.1\r\n\r\nGET /c HTTP/1.1\r\n";
+
+        let mut parser = HttpRequestParser::new();
+        assert!(parser.take_request().is_none());
+
+        match parser.parse_bytes(msg).unwrap() {
+            HttpRequestParserState::Complete => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+        let req = parser.take_request().unwrap();
+        assert_eq!(req.url, "/a");
+        assert_eq!(&req.body[..], b"abc");
+
+        match parser.parse_bytes(&[]).unwrap() {
+            HttpRequestParserState::Complete => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+        let req = parser.take_request().unwrap();
+        assert_eq!(req.url, "/b");
+        assert_eq!(req.body.len(), 0);
+
+        match parser.parse_bytes(&[]).unwrap() {
+            HttpRequestParserState::MoreDataRequired => (),
+            s => panic!("expected more data, got {:?}", s)
+        }
+        assert!(parser.take_request().is_none());
+        match parser.parse_bytes(b"\r\n").unwrap() {
+            HttpRequestParserState::Complete => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+        assert_eq!(parser.take_request().unwrap().url, "/c");
+    }
+
+    #[test]
+    pub fn test_empty_lines_before_request() {
+        let mut parser = HttpRequestParser::new();
+        match parser.parse_bytes(b"\r\n\r\nGET /x HTTP/1.1\r\n\r\n").unwrap() {
+            HttpRequestParserState::Complete => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+        assert_eq!(parser.take_request().unwrap().url, "/x");
+
+        // the CRLF some clients add after a POST body doesn't start another request
+        let mut parser = HttpRequestParser::new();
+        match parser.parse_bytes(b"POST /a HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc\r\n").unwrap() {
+            HttpRequestParserState::Complete => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+        assert_eq!(parser.take_request().unwrap().url, "/a");
+        match parser.parse_bytes(&[]).unwrap() {
+            HttpRequestParserState::MoreDataRequired => (),
+            s => panic!("expected more data, got {:?}", s)
+        }
+        match parser.parse_bytes(b"GET /b HTTP/1.1\r\n\r\n").unwrap() {
+            HttpRequestParserState::Complete => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+        assert_eq!(parser.take_request().unwrap().url, "/b");
+    }
+
+    #[test]
+    pub fn test_chunk_sizes() {
+        let msg = b"POST /upload HTTP/1.1\r\nHost: a\r\nContent-Length: 10\r\n\r\n0123456789GET";
+
+        // every split must give the same request, including a CR and its LF in different chunks
+        for size in vec![1, 2, 3, 5, 7, 16, 4096] {
+            let mut parser = HttpRequestParser::new();
+            for chunk in msg.chunks(size) {
+                parser.parse_bytes(chunk).unwrap();
+            }
+
+            assert!(parser.is_complete(), "chunk size {}", size);
+            assert_eq!(parser.surplus(), b"GET");
+            let req = parser.take_request().unwrap();
+            assert_eq!(req.url, "/upload");
+            assert_eq!(req.headers.len(), 2);
+            assert_eq!(&req.body[..], b"0123456789");
+        }
+    }
+
+    #[test]
+    pub fn test_header_scanning() {
+        let mut parser = HttpRequestParser::new();
+        parser.parse_bytes(b"GET / HTTP/1.1\r\nHost: example.com\r").unwrap();
+        // completed lines are discarded, only the one in progress is kept
+        assert_eq!(&parser.scanner.line[..], b"Host: example.com");
+        assert_eq!(parser.scanner.state, ScanState::AfterCr);
+
+        parser.parse_bytes(b"\nX-Data: a").unwrap();
+        assert_eq!(&parser.scanner.line[..], b"X-Data: a");
+        assert_eq!(parser.get_request().headers.get("Host").unwrap(), "example.com");
+
+        parser.parse_bytes(b"b\r").unwrap();
+        parser.parse_bytes(b"\n\r").unwrap();
+        match parser.parse_bytes(b"\n").unwrap() {
+            HttpRequestParserState::Complete => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+        assert_eq!(parser.get_request().headers.get("X-Data").unwrap(), "ab");
+        assert_eq!(parser.scanner.line.len(), 0);
+    }
+
+    #[test]
+    pub fn test_line_endings() {
+        let msg = b"GET /nc HTTP/1.1\nHost: a\r\nAccept: */*\n\n";
+
+        let mut parser = HttpRequestParser::new();
+        match parser.parse_bytes(msg) {
+            Err(HttpRequestParserError::BareLineEnding) => (),
+            r => panic!("expected BareLineEnding, got {:?}", r)
+        }
+
+        let mut parser = HttpRequestParser::new();
+        parser.set_line_ending_policy(LineEndingPolicy::AllowBareLf);
+        for b in msg.iter() {
+            parser.parse_bytes(&[*b]).unwrap();
+        }
+        assert!(parser.is_complete());
+        assert_eq!(parser.get_request().url, "/nc");
+        assert_eq!(parser.get_request().headers.len(), 2);
+
+        // a CR has to be followed by LF either way
+        for policy in vec![LineEndingPolicy::Strict, LineEndingPolicy::AllowBareLf] {
+            let mut parser = HttpRequestParser::new();
+            parser.set_line_ending_policy(policy);
+            match parser.parse_bytes(b"GET / HTTP/1.1\r\nHost: a\rX-Smuggled: b\r\n\r\n") {
+                Err(HttpRequestParserError::BareLineEnding) => (),
+                r => panic!("expected BareLineEnding, got {:?}", r)
+            }
+        }
+
+        // the chunked body follows the same policy
+        let msg = b"POST / HTTP/1.1\nTransfer-Encoding: chunked\n\n1\nx\n0\n\n";
+        let mut parser = HttpRequestParser::new();
+        parser.set_line_ending_policy(LineEndingPolicy::AllowBareLf);
+        match parser.parse_bytes(msg).unwrap() {
+            HttpRequestParserState::Complete => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+        assert_eq!(&parser.get_request().body[..], b"x");
+
+        let mut parser = HttpRequestParser::new();
+        match parser.parse_bytes(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\nX: a\rb\r\n\r\n") {
+            Err(HttpRequestParserError::ChunkedEncodingError(ChunkedDecoderError::BareLineEnding)) => (),
+            r => panic!("expected BareLineEnding, got {:?}", r)
+        }
+    }
+
+    #[test]
+    pub fn test_request_methods() {
+        let methods = [("PUT", HttpMethod::Put), ("DELETE", HttpMethod::Delete), ("OPTIONS", HttpMethod::Options),
+                       ("PATCH", HttpMethod::Patch), ("TRACE", HttpMethod::Trace), ("CONNECT", HttpMethod::Connect),
+                       ("PROPFIND", HttpMethod::Extension(String::from("PROPFIND")))];
+
+        for &(ref token, ref method) in methods.iter() {
+            let mut parser = HttpRequestParser::new();
+            parser.parse_bytes(format!("{} /dav HTTP/1.1\r\n\r\n", token).as_bytes()).unwrap();
+
+            let req = parser.get_request();
+            assert_eq!(&req.method, method);
+            assert_eq!(req.method.as_str(), *token);
+            assert_eq!(req.url, "/dav");
+        }
+    }
+
+    #[test]
+    pub fn test_request_line_tokenizer() {
+        assert_eq!(parse_request_line("GET /a?b=c HTTP/1.1"), Ok(RequestLine { method: "GET", target: "/a?b=c", version: "1.1" }));
+        assert_eq!(parse_request_line("M-SEARCH * HTTP/1.0"), Ok(RequestLine { method: "M-SEARCH", target: "*", version: "1.0" }));
+
+        assert_eq!(parse_request_line(""), Err(RequestLineError::InvalidMethod));
+        assert_eq!(parse_request_line("GE(T / HTTP/1.1"), Err(RequestLineError::InvalidMethod));
+        assert_eq!(parse_request_line(" / HTTP/1.1"), Err(RequestLineError::InvalidMethod));
+        assert_eq!(parse_request_line("GET"), Err(RequestLineError::InvalidTarget));
+        assert_eq!(parse_request_line("GET  /a HTTP/1.1"), Err(RequestLineError::InvalidTarget));
+        assert_eq!(parse_request_line("GET /a"), Err(RequestLineError::InvalidVersion));
+        assert_eq!(parse_request_line("GET /a HTTP/1.1 "), Err(RequestLineError::InvalidVersion));
+        assert_eq!(parse_request_line("GET /a b HTTP/1.1"), Err(RequestLineError::InvalidVersion));
+        assert_eq!(parse_request_line("GET /a HTTP/11"), Err(RequestLineError::InvalidVersion));
+        assert_eq!(parse_request_line("GET /a http/1.1"), Err(RequestLineError::InvalidVersion));
+
+        let mut parser = HttpRequestParser::new();
+        match parser.parse_bytes(b"GETX / HTTP/1.1\r\n") {
+            Ok(_) => assert_eq!(parser.get_request().method, HttpMethod::Extension(String::from("GETX"))),
+            Err(e) => panic!("unexpected error {:?}", e)
+        }
+
+        // the target is parsed once, here, and kept for routing
+        let mut parser = HttpRequestParser::new();
+        parser.parse_bytes(b"GET /a%2Fb/c?x HTTP/1.1\r\n").unwrap();
+        let uri = parser.get_request().uri.as_ref().unwrap();
+        assert_eq!(uri.segments, vec![String::from("a/b"), String::from("c")]);
+        assert_eq!(uri.query, Some(String::from("x")));
+
+        let mut parser = HttpRequestParser::new();
+        match parser.parse_bytes(b"GET  /a HTTP/1.1\r\n") {
+            Err(HttpRequestParserError::InvalidRequestLine(RequestLineError::InvalidTarget)) => (),
+            r => panic!("expected an invalid target, got {:?}", r)
+        }
+    }
+
+    #[test]
+    pub fn test_chunked_request_parsing() {
+        let msg = "POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWiki\r\n5\r\npedia\r\n0\r\nChecksum: abc\r\n\r\n";
+
+        let mut parser = HttpRequestParser::new();
+        let mut state = None;
+        for b in msg.bytes() {
+            assert_eq!(parser.read_how_many_bytes(), 1);
+            state = Some(parser.parse_bytes(&[b]).unwrap());
+        }
+
+        match state {
+            Some(HttpRequestParserState::Complete) => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+        assert_eq!(parser.read_how_many_bytes(), 0);
+
+        let req = parser.get_request();
+        assert_eq!(&req.body[..], b"Wikipedia");
+        assert_eq!(req.trailers.get("Checksum").unwrap(), "abc");
+        // trailers don't end up with the headers, they could change the framing there
+        assert!(req.get_raw_header("Checksum").is_none());
+    }
+
+    #[test]
+    pub fn test_request_smuggling() {
+        fn parse_error(msg: &[u8]) -> HttpRequestParserError {
+            match HttpRequestParser::new().parse_bytes(msg) {
+                Err(e) => e,
+                r => panic!("expected an error, got {:?}", r)
+            }
+        }
+
+        match parse_error(b"POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 4\r\n\r\nabcd") {
+            HttpRequestParserError::ConflictingContentLength => (),
+            e => panic!("expected ConflictingContentLength, got {:?}", e)
+        }
+        match parse_error(b"POST / HTTP/1.1\r\nContent-Length: 3, 4\r\n\r\nabcd") {
+            HttpRequestParserError::ConflictingContentLength => (),
+            e => panic!("expected ConflictingContentLength, got {:?}", e)
+        }
+        match parse_error(b"POST / HTTP/1.1\r\nContent-Length: 4\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n") {
+            HttpRequestParserError::ContentLengthWithTransferEncoding => (),
+            e => panic!("expected ContentLengthWithTransferEncoding, got {:?}", e)
+        }
+        for length in vec!["+3", "-3", "3.0", "0x3", "", "99999999999"] {
+            let msg = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\nabc", length);
+            match parse_error(msg.as_bytes()) {
+                HttpRequestParserError::InvalidContentLength => (),
+                e => panic!("expected InvalidContentLength for {:?}, got {:?}", length, e)
+            }
+        }
+        // chunked has to be the final coding over all fields
+        match parse_error(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: gzip\r\n\r\n") {
+            HttpRequestParserError::UnsupportedTransferEncoding => (),
+            e => panic!("expected UnsupportedTransferEncoding, got {:?}", e)
+        }
+        match parse_error(b"GET / HTTP/1.1\r\nContent-Length : 3\r\n\r\nabc") {
+            HttpRequestParserError::WhitespaceInHeaderName => (),
+            e => panic!("expected WhitespaceInHeaderName, got {:?}", e)
+        }
+
+        // repeated identical lengths are fine
+        let mut parser = HttpRequestParser::new();
+        match parser.parse_bytes(b"POST / HTTP/1.1\r\nContent-Length: 3, 3\r\nContent-Length: 3\r\n\r\nabcGET").unwrap() {
+            HttpRequestParserState::Complete => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+        assert_eq!(&parser.get_request().body[..], b"abc");
+        assert_eq!(parser.get_request().headers.get_all("Content-Length").count(), 1);
+        assert_eq!(parser.surplus(), b"GET");
+
+        let mut parser = HttpRequestParser::new();
+        match parser.parse_bytes(b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n1\r\nx\r\n0\r\n\r\n").unwrap() {
+            HttpRequestParserState::Complete => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+        assert_eq!(&parser.get_request().body[..], b"x");
+    }
+
+    #[test]
+    pub fn test_parser_limits() {
+        let limits = ParserLimits {
+            max_request_line: 16,
+            max_header_count: 2,
+            max_header_bytes: 32,
+            max_body_size: 8
+        };
+
+        fn parse(limits: ParserLimits, msg: &[u8]) -> Result<HttpRequestParserState, HttpRequestParserError> {
+            let mut parser = HttpRequestParser::with_limits(limits);
+            let mut r = Ok(HttpRequestParserState::MoreDataRequired);
+            for b in msg {
+                r = parser.parse_bytes(&[*b]);
+                if r.is_err() { break; }
+            }
+            r
+        }
+
+        match parse(limits, b"GET /0123456789abcdef HTTP/1.1\r\n") {
+            Err(HttpRequestParserError::RequestLineTooLong) => (),
+            r => panic!("expected RequestLineTooLong, got {:?}", r)
+        }
+        // never terminated, still caught
+        match parse(limits, b"GET /0123456789abcdef") {
+            Err(HttpRequestParserError::RequestLineTooLong) => (),
+            r => panic!("expected RequestLineTooLong, got {:?}", r)
+        }
+        match parse(limits, b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n") {
+            Err(HttpRequestParserError::TooManyHeaders) => (),
+            r => panic!("expected TooManyHeaders, got {:?}", r)
+        }
+        match parse(limits, b"GET / HTTP/1.1\r\nA: 0123456789012345678901234567890123456789") {
+            Err(HttpRequestParserError::HeadersTooLarge) => (),
+            r => panic!("expected HeadersTooLarge, got {:?}", r)
+        }
+        match parse(limits, b"POST / HTTP/1.1\r\nContent-Length: 9\r\n\r\n") {
+            Err(HttpRequestParserError::PayloadTooLarge) => (),
+            r => panic!("expected PayloadTooLarge, got {:?}", r)
+        }
+        match parse(limits, b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n9\r\n012345678\r\n") {
+            Err(HttpRequestParserError::PayloadTooLarge) => (),
+            r => panic!("expected PayloadTooLarge, got {:?}", r)
+        }
+        // chunk extensions and trailers are held to the limits as well
+        match parse(limits, b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1;0123456789abcdef") {
+            Err(HttpRequestParserError::ChunkedEncodingError(ChunkedDecoderError::ChunkSizeLineTooLong)) => (),
+            r => panic!("expected ChunkSizeLineTooLong, got {:?}", r)
+        }
+        match parse(limits, b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n") {
+            Err(e @ HttpRequestParserError::ChunkedEncodingError(ChunkedDecoderError::TooManyTrailers)) => {
+                assert_eq!(e.status_code(), StatusCode::RequestHeaderFieldsTooLarge);
+            },
+            r => panic!("expected TooManyTrailers, got {:?}", r)
+        }
+        match parse(limits, b"POST / HTTP/1.1\r\nContent-Length: 8\r\n\r\n01234567") {
+            Ok(HttpRequestParserState::Complete) => (),
+            r => panic!("expected a complete request, got {:?}", r)
+        }
+    }
+
+    #[test]
+    pub fn test_header_case_and_repeats() {
+        let msg = b"POST /form HTTP/1.1\r\ncontent-length: 2\r\nCookie: a=1\r\nCookie: b=2\r\n\r\nok";
+
+        let mut parser = HttpRequestParser::new();
+        match parser.parse_bytes(msg).unwrap() {
+            HttpRequestParserState::Complete => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+
+        let req = parser.get_request();
+        assert_eq!(req.content_length(), Some(2));
+        assert_eq!(req.headers.get_all("cookie").collect::<Vec<_>>(), vec!["a=1", "b=2"]);
+    }
+
+    #[test]
+    pub fn test_header_line_parsing() {
+        let mut h = HeaderMap::new();
+        parse_header_line(&mut h, "Host:example.com", ObsFoldPolicy::Reject).unwrap();
+        parse_header_line(&mut h, "Accept: \t text/html \t", ObsFoldPolicy::Reject).unwrap();
+        parse_header_line(&mut h, "X-Empty:", ObsFoldPolicy::Reject).unwrap();
+        assert_eq!(h.get("Host").unwrap(), "example.com");
+        assert_eq!(h.get("Accept").unwrap(), "text/html");
+        assert_eq!(h.get("X-Empty").unwrap(), "");
+
+        assert!(parse_header_line(&mut h, "Host : example.com", ObsFoldPolicy::Reject).is_err());
+        assert!(parse_header_line(&mut h, ": example.com", ObsFoldPolicy::Reject).is_err());
+        assert!(parse_header_line(&mut h, "no separator", ObsFoldPolicy::Reject).is_err());
+        assert!(parse_header_line(&mut h, " folded", ObsFoldPolicy::Reject).is_err());
+
+        // only token characters in names, a proxy trimming other whitespace would see another header
+        for line in vec!["Transfer-Encoding\x0b: chunked", "Transfer-Encoding\x0c: chunked", "X(y): a", "X\0: a", "Caf\u{e9}: a"] {
+            match parse_header_line(&mut h, line, ObsFoldPolicy::Reject) {
+                Err(HttpRequestParserError::HeaderError) => (),
+                r => panic!("{:?} should be rejected, got {:?}", line, r)
+            }
+        }
+        for line in vec!["X: a\0b", "X: a\x7fb", "X: a\x0bb"] {
+            match parse_header_line(&mut h, line, ObsFoldPolicy::Reject) {
+                Err(HttpRequestParserError::HeaderError) => (),
+                r => panic!("{:?} should be rejected, got {:?}", line, r)
+            }
+        }
+        let mut tab = HeaderMap::new();
+        parse_header_line(&mut tab, "X-Tab: a\tb", ObsFoldPolicy::Reject).unwrap();
+        assert_eq!(tab.get("X-Tab").unwrap(), "a\tb");
+
+        let mut parser = HttpRequestParser::new();
+        match parser.parse_bytes(b"POST / HTTP/1.1\r\nTransfer-Encoding\x0b: chunked\r\n\r\n") {
+            Err(HttpRequestParserError::HeaderError) => (),
+            r => panic!("expected HeaderError, got {:?}", r)
+        }
+
+        parse_header_line(&mut h, "\t  continued  ", ObsFoldPolicy::Unfold).unwrap();
+        assert_eq!(h.get("X-Empty").unwrap(), "continued");
+        parse_header_line(&mut h, " more", ObsFoldPolicy::Unfold).unwrap();
+        assert_eq!(h.get("X-Empty").unwrap(), "continued more");
+
+        assert!(parse_header_line(&mut HeaderMap::new(), " folded", ObsFoldPolicy::Unfold).is_err());
+
+        let mut parser = HttpRequestParser::new();
+        parser.set_obs_fold_policy(ObsFoldPolicy::Unfold);
+        parser.parse_bytes(b"GET / HTTP/1.1\r\nX-Long: a\r\n b\r\n\r\n").unwrap();
+        assert_eq!(parser.get_request().headers.get("X-Long").unwrap(), "a b");
+    }
 }
@@ -0,0 +1,544 @@
+use core::cmp::min;
+use core::mem;
+use core::str::from_utf8;
+use http::*;
+use chunked::*;
+use parser::*;
+use scanner::*;
+use status::StatusCode;
+use collections::vec::*;
+use collections::String;
+use collections::string::ToString;
+
+/// Incremental parser for responses, the client side counterpart of `HttpRequestParser`.
+///
+/// Held to the same `ParserLimits`, `max_request_line` bounding the status line,
+/// so a misbehaving server can't exhaust the memory of the device either.
+/// Interim 1xx responses are skipped, except for 101 which ends the exchange.
+pub struct HttpResponseParser {
+    limits: ParserLimits,
+    header_bytes: usize,
+    scanner: LineScanner,
+    line_num: u16,
+    headers_parsed: bool,
+    request_method: Option<HttpMethod>,
+    body_kind: ResponseBody,
+    complete: bool,
+    surplus: Vec<u8>,
+    msg: HttpResponseMessage,
+}
+
+/// How the body of the response being parsed is delimited.
+enum ResponseBody {
+    Empty,
+    Length(usize),
+    Chunked(ChunkedDecoder),
+    UntilClose,
+}
+
+#[derive(Debug)]
+pub enum HttpResponseParserState {
+    MoreDataRequired,
+    Complete,
+}
+
+#[derive(Debug)]
+pub enum HttpResponseParserError {
+    InvalidString,
+    InvalidStatusLine,
+    HeaderError,
+    ChunkedEncodingError(ChunkedDecoderError),
+    /// `Content-Length` and `Transfer-Encoding` don't agree on the end of the body,
+    /// checked like they are for requests.
+    InvalidFraming(HttpRequestParserError),
+    /// A CR not followed by LF, or a LF without a CR when those aren't allowed.
+    BareLineEnding,
+    StatusLineTooLong,
+    TooManyHeaders,
+    HeadersTooLarge,
+    /// A body longer than `max_body_size`.
+    BodyTooLarge,
+    /// The connection was closed before the end of the response.
+    IncompleteResponse,
+}
+
+impl HttpResponseParser {
+    pub fn new() -> HttpResponseParser {
+        HttpResponseParser::with_limits(ParserLimits::default())
+    }
+
+    pub fn with_limits(limits: ParserLimits) -> HttpResponseParser {
+        HttpResponseParser {
+            limits: limits,
+            header_bytes: 0,
+            scanner: LineScanner::new(),
+            line_num: 0,
+            headers_parsed: false,
+            request_method: None,
+            body_kind: ResponseBody::Empty,
+            complete: false,
+            surplus: Vec::new(),
+            msg: HttpResponseMessage::new(StatusCode::Ok)
+        }
+    }
+
+    /// Parser for the response to a request with `method`, as responses to `HEAD` have no body.
+    pub fn for_request(method: &HttpMethod) -> HttpResponseParser {
+        let mut p = HttpResponseParser::new();
+        p.request_method = Some(method.clone());
+        p
+    }
+
+    pub fn set_line_ending_policy(&mut self, policy: LineEndingPolicy) {
+        self.scanner.line_endings = policy;
+    }
+
+    pub fn are_headers_parsed(&self) -> bool {
+        self.headers_parsed
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Bytes received past the end of the complete response.
+    pub fn surplus(&self) -> &[u8] {
+        &self.surplus
+    }
+
+    pub fn take_surplus(&mut self) -> Vec<u8> {
+        mem::replace(&mut self.surplus, Vec::new())
+    }
+
+    pub fn get_response(&self) -> &HttpResponseMessage {
+        &self.msg
+    }
+
+    pub fn into_response(self) -> HttpResponseMessage {
+        self.msg
+    }
+
+    pub fn parse_bytes(&mut self, data: &[u8]) -> Result<HttpResponseParserState, HttpResponseParserError> {
+        if self.complete {
+            self.surplus.push_all(data);
+            return Ok(HttpResponseParserState::Complete);
+        }
+
+        let mut i = 0;
+        while i < data.len() && !self.headers_parsed {
+            match self.scanner.scan(&data[i..]) {
+                ScanResult::Line(n) => {
+                    i += n;
+                    try!(self.end_of_line());
+                },
+                ScanResult::Partial => i = data.len(),
+                ScanResult::BareLineEnding => return Err(HttpResponseParserError::BareLineEnding)
+            }
+
+            // the line in progress must not grow past the limits either
+            if self.line_num == 0 && self.scanner.line.len() > self.limits.max_request_line {
+                return Err(HttpResponseParserError::StatusLineTooLong);
+            }
+            if self.line_num > 0 && self.header_bytes + self.scanner.line.len() > self.limits.max_header_bytes {
+                return Err(HttpResponseParserError::HeadersTooLarge);
+            }
+        }
+
+        if self.headers_parsed {
+            let data = &data[i..];
+            let c = try!(self.parse_body(data));
+            self.surplus.push_all(&data[c..]);
+
+            if self.complete {
+                if let ResponseBody::Chunked(ref d) = self.body_kind {
+                    self.msg.trailers = d.trailers().clone();
+                }
+                return Ok(HttpResponseParserState::Complete);
+            }
+        }
+
+        Ok(HttpResponseParserState::MoreDataRequired)
+    }
+
+    /// The line in the scanner is complete, parse it and make room for the next one.
+    fn end_of_line(&mut self) -> Result<(), HttpResponseParserError> {
+        if self.scanner.line.len() == 0 && self.line_num > 0 {
+            let code = self.msg.response_code;
+            if code.is_informational() && code != StatusCode::SwitchingProtocols {
+                // an interim response like 100 Continue, the final one follows, RFC 7231 section 6.2
+                self.msg = HttpResponseMessage::new(StatusCode::Ok);
+                self.line_num = 0;
+                self.header_bytes = 0;
+                return Ok(());
+            }
+
+            self.headers_parsed = true;
+            return self.start_body();
+        }
+
+        {
+            let str = from_utf8(&self.scanner.line);
+            if !str.is_ok() { return Err(HttpResponseParserError::InvalidString); }
+            let str = str.unwrap();
+
+            if self.line_num == 0 {
+                if str.len() > self.limits.max_request_line {
+                    return Err(HttpResponseParserError::StatusLineTooLong);
+                }
+                try!(parse_status_line(&mut self.msg, str));
+            } else {
+                self.header_bytes += str.len() + 2;
+                if self.header_bytes > self.limits.max_header_bytes {
+                    return Err(HttpResponseParserError::HeadersTooLarge);
+                }
+                try!(parse_header_line(&mut self.msg.headers, str, ObsFoldPolicy::Unfold).map_err(|_| HttpResponseParserError::HeaderError));
+                if self.msg.headers.len() > self.limits.max_header_count {
+                    return Err(HttpResponseParserError::TooManyHeaders);
+                }
+            }
+        }
+
+        self.line_num += 1;
+        self.scanner.line.clear();
+        Ok(())
+    }
+
+    /// Feed body bytes to whatever delimits the body, returning how many were used.
+    fn parse_body(&mut self, data: &[u8]) -> Result<usize, HttpResponseParserError> {
+        let consumed = match self.body_kind {
+            ResponseBody::Empty => {
+                self.complete = true;
+                0
+            },
+            ResponseBody::Length(length) => {
+                let c = min(length - self.msg.body.len(), data.len());
+                self.msg.body.push_all(&data[..c]);
+                self.complete = self.msg.body.len() == length;
+                c
+            },
+            ResponseBody::Chunked(ref mut d) => {
+                let c = try!(d.decode(data, &mut self.msg.body).map_err(HttpResponseParserError::ChunkedEncodingError));
+                self.complete = d.is_complete();
+                c
+            },
+            ResponseBody::UntilClose => {
+                self.msg.body.push_all(data);
+                data.len()
+            }
+        };
+
+        if self.msg.body.len() > self.limits.max_body_size {
+            return Err(HttpResponseParserError::BodyTooLarge);
+        }
+
+        Ok(consumed)
+    }
+
+    /// Signal that the connection was closed. This completes a response whose body
+    /// is delimited by the end of the connection, anything else still unfinished is an error.
+    pub fn finish(&mut self) -> Result<HttpResponseParserState, HttpResponseParserError> {
+        if let ResponseBody::UntilClose = self.body_kind {
+            self.complete = true;
+        }
+
+        if self.complete {
+            Ok(HttpResponseParserState::Complete)
+        } else {
+            Err(HttpResponseParserError::IncompleteResponse)
+        }
+    }
+
+    /// Decide how the body is delimited, RFC 7230 section 3.3.3.
+    fn start_body(&mut self) -> Result<(), HttpResponseParserError> {
+        let code = self.msg.response_code;
+        if self.request_method == Some(HttpMethod::Head) || code.is_informational() ||
+           code == StatusCode::NoContent || code == StatusCode::NotModified {
+            return Ok(());
+        }
+
+        let length = try!(check_body_framing(&mut self.msg.headers).map_err(HttpResponseParserError::InvalidFraming));
+        if self.msg.is_chunked() {
+            let mut decoder = ChunkedDecoder::with_limits(self.limits);
+            decoder.set_line_ending_policy(self.scanner.line_endings);
+            self.body_kind = ResponseBody::Chunked(decoder);
+        } else if let Some(length) = length {
+            if length as usize > self.limits.max_body_size {
+                return Err(HttpResponseParserError::BodyTooLarge);
+            }
+            self.body_kind = ResponseBody::Length(length as usize);
+        } else {
+            // no length, or a transfer-coding other than chunked last, RFC 7230 section 3.3.3
+            self.body_kind = ResponseBody::UntilClose;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse `HTTP-version SP status-code SP reason-phrase`. A missing reason phrase is tolerated.
+fn parse_status_line(msg: &mut HttpResponseMessage, line: &str) -> Result<(), HttpResponseParserError> {
+    let b = line.as_bytes();
+    if b.len() < 12 || !line.starts_with("HTTP/") || !is_digit(b[5]) || b[6] != b'.' || !is_digit(b[7]) || b[8] != b' ' {
+        return Err(HttpResponseParserError::InvalidStatusLine);
+    }
+
+    // the bytes are checked first, slicing the str in the middle of a character would panic
+    if !b[9..12].iter().all(|&c| is_digit(c)) || (b.len() > 12 && b[12] != b' ') {
+        return Err(HttpResponseParserError::InvalidStatusLine);
+    }
+    let code = line[9..12].parse::<u16>();
+    if !code.is_ok() {
+        return Err(HttpResponseParserError::InvalidStatusLine);
+    }
+
+    msg.http_version = line[5..8].to_string();
+    msg.response_code = StatusCode::from_u16(code.unwrap());
+    msg.response_status = if b.len() > 12 { line[13..].to_string() } else { String::new() };
+
+    Ok(())
+}
+
+#[inline]
+fn is_digit(b: u8) -> bool {
+    b >= b'0' && b <= b'9'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::*;
+    use parser::*;
+    use status::StatusCode;
+    use collections::vec::*;
+
+    fn parse_all(parser: &mut HttpResponseParser, data: &[u8]) -> HttpResponseParserState {
+        let mut state = HttpResponseParserState::MoreDataRequired;
+        for b in data {
+            state = parser.parse_bytes(&[*b]).unwrap();
+        }
+        state
+    }
+
+    #[test]
+    pub fn test_response_parsing() {
+        let mut parser = HttpResponseParser::new();
+        match parse_all(&mut parser, b"HTTP/1.1 404 Not Found\r\nContent-Length: 3\r\n\r\nabcHTTP") {
+            HttpResponseParserState::Complete => (),
+            s => panic!("expected a complete response, got {:?}", s)
+        }
+        {
+            let r = parser.get_response();
+            assert_eq!(r.http_version, "1.1");
+            assert_eq!(r.response_code, StatusCode::NotFound);
+            assert_eq!(r.response_status, "Not Found");
+            assert_eq!(&r.body[..], b"abc");
+        }
+        assert_eq!(parser.surplus(), b"HTTP");
+
+        let mut parser = HttpResponseParser::new();
+        match parse_all(&mut parser, b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n4\r\nWiki\r\n0\r\nSet-Cookie: a=b\r\nContent-Length: 1\r\n\r\n") {
+            HttpResponseParserState::Complete => (),
+            s => panic!("expected a complete response, got {:?}", s)
+        }
+        {
+            let r = parser.get_response();
+            assert_eq!(&r.body[..], b"Wiki");
+            // trailers can't pose as headers
+            assert_eq!(r.trailers.get("Set-Cookie").unwrap(), "a=b");
+            assert!(!r.headers.contains_key("Set-Cookie"));
+            assert!(!r.headers.contains_key("Content-Length"));
+        }
+
+        let mut parser = HttpResponseParser::new();
+        match parse_all(&mut parser, b"HTTP/1.0 200\r\n\r\nuntil the end") {
+            HttpResponseParserState::MoreDataRequired => (),
+            s => panic!("expected more data, got {:?}", s)
+        }
+        assert!(parser.finish().is_ok());
+        assert_eq!(parser.get_response().response_status, "");
+        assert_eq!(&parser.get_response().body[..], b"until the end");
+
+        let mut parser = HttpResponseParser::for_request(&HttpMethod::Head);
+        match parse_all(&mut parser, b"HTTP/1.1 200 OK\r\nContent-Length: 300\r\n\r\n") {
+            HttpResponseParserState::Complete => (),
+            s => panic!("expected a complete response, got {:?}", s)
+        }
+
+        let mut parser = HttpResponseParser::new();
+        parse_all(&mut parser, b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\na");
+        assert!(parser.finish().is_err());
+
+        assert!(HttpResponseParser::new().parse_bytes(b"HTTP/1.1 2000 OK\r\n").is_err());
+        assert!(HttpResponseParser::new().parse_bytes(b"ICY 200 OK\r\n").is_err());
+        assert!(HttpResponseParser::new().parse_bytes("HTTP/1.1 12é OK\r\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    pub fn test_interim_responses() {
+        let msg = b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 103 Early Hints\r\nLink: </a.css>\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        for chunk in vec![1, msg.len()] {
+            let mut parser = HttpResponseParser::new();
+            let mut state = HttpResponseParserState::MoreDataRequired;
+            for part in msg.chunks(chunk) {
+                state = parser.parse_bytes(part).unwrap();
+            }
+            match state {
+                HttpResponseParserState::Complete => (),
+                s => panic!("expected a complete response, got {:?}", s)
+            }
+
+            let r = parser.get_response();
+            assert_eq!(r.response_code, StatusCode::Ok);
+            assert!(!r.headers.contains_key("Link"));
+            assert_eq!(&r.body[..], b"ok");
+        }
+
+        // 101 is final, what follows belongs to the new protocol
+        let mut parser = HttpResponseParser::new();
+        match parser.parse_bytes(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n\x81\x00").unwrap() {
+            HttpResponseParserState::Complete => (),
+            s => panic!("expected a complete response, got {:?}", s)
+        }
+        assert_eq!(parser.get_response().response_code, StatusCode::SwitchingProtocols);
+        assert_eq!(parser.surplus(), b"\x81\x00");
+    }
+
+    #[test]
+    pub fn test_response_limits() {
+        let mut limits = ParserLimits::default();
+        limits.max_request_line = 32;
+        limits.max_header_count = 2;
+        limits.max_header_bytes = 64;
+        limits.max_body_size = 8;
+
+        fn parse(limits: ParserLimits, data: &[u8]) -> Result<HttpResponseParserState, HttpResponseParserError> {
+            let mut parser = HttpResponseParser::with_limits(limits);
+            parser.parse_bytes(data)
+        }
+
+        // a header line without an end is refused once it's too long, not buffered
+        let mut endless = b"HTTP/1.1 200 OK\r\nX: ".to_vec();
+        endless.push_all(&vec![b'a'; 1000000]);
+        match parse(limits, &endless) {
+            Err(HttpResponseParserError::HeadersTooLarge) => (),
+            r => panic!("expected HeadersTooLarge, got {:?}", r)
+        }
+        match parse(limits, b"HTTP/1.1 200 OK, or so the server says\r\n") {
+            Err(HttpResponseParserError::StatusLineTooLong) => (),
+            r => panic!("expected StatusLineTooLong, got {:?}", r)
+        }
+        match parse(limits, b"HTTP/1.1 200 OK\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n") {
+            Err(HttpResponseParserError::TooManyHeaders) => (),
+            r => panic!("expected TooManyHeaders, got {:?}", r)
+        }
+        match parse(limits, b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\n") {
+            Err(HttpResponseParserError::BodyTooLarge) => (),
+            r => panic!("expected BodyTooLarge, got {:?}", r)
+        }
+        match parse(limits, b"HTTP/1.1 200 OK\r\n\r\n0123456789") {
+            Err(HttpResponseParserError::BodyTooLarge) => (),
+            r => panic!("expected BodyTooLarge, got {:?}", r)
+        }
+        match parse(limits, b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\n01234\r\n5\r\n56789\r\n") {
+            Err(HttpResponseParserError::BodyTooLarge) => (),
+            r => panic!("expected BodyTooLarge, got {:?}", r)
+        }
+    }
+
+    #[test]
+    pub fn test_response_line_endings() {
+        let msg = b"HTTP/1.1 200 OK\nContent-Length: 2\n\nok";
+        match HttpResponseParser::new().parse_bytes(msg) {
+            Err(HttpResponseParserError::BareLineEnding) => (),
+            r => panic!("expected BareLineEnding, got {:?}", r)
+        }
+
+        let mut parser = HttpResponseParser::new();
+        parser.set_line_ending_policy(LineEndingPolicy::AllowBareLf);
+        match parse_all(&mut parser, msg) {
+            HttpResponseParserState::Complete => (),
+            s => panic!("expected a complete response, got {:?}", s)
+        }
+        assert_eq!(&parser.get_response().body[..], b"ok");
+
+        match HttpResponseParser::new().parse_bytes(b"HTTP/1.1 200 OK\r\nX: a\rb\r\n\r\n") {
+            Err(HttpResponseParserError::BareLineEnding) => (),
+            r => panic!("expected BareLineEnding, got {:?}", r)
+        }
+    }
+
+    #[test]
+    pub fn test_response_framing() {
+        match HttpResponseParser::new().parse_bytes(b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\nContent-Length: 5\r\n\r\nabc") {
+            Err(HttpResponseParserError::InvalidFraming(HttpRequestParserError::ConflictingContentLength)) => (),
+            r => panic!("expected ConflictingContentLength, got {:?}", r)
+        }
+        match HttpResponseParser::new().parse_bytes(b"HTTP/1.1 200 OK\r\nContent-Length: 3x\r\n\r\nabc") {
+            Err(HttpResponseParserError::InvalidFraming(HttpRequestParserError::InvalidContentLength)) => (),
+            r => panic!("expected InvalidContentLength, got {:?}", r)
+        }
+        match HttpResponseParser::new().parse_bytes(b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n") {
+            Err(HttpResponseParserError::InvalidFraming(HttpRequestParserError::ContentLengthWithTransferEncoding)) => (),
+            r => panic!("expected ContentLengthWithTransferEncoding, got {:?}", r)
+        }
+
+        // repeated identical lengths are fine
+        let mut parser = HttpResponseParser::new();
+        match parse_all(&mut parser, b"HTTP/1.1 200 OK\r\nContent-Length: 3, 3\r\nContent-Length: 3\r\n\r\nabc") {
+            HttpResponseParserState::Complete => (),
+            s => panic!("expected a complete response, got {:?}", s)
+        }
+        assert_eq!(parser.get_response().headers.get("Content-Length").unwrap(), "3");
+
+        // chunked in a later field still counts
+        let mut parser = HttpResponseParser::new();
+        match parse_all(&mut parser, b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n1\r\nx\r\n0\r\n\r\n") {
+            HttpResponseParserState::Complete => (),
+            s => panic!("expected a complete response, got {:?}", s)
+        }
+    }
+
+    #[test]
+    pub fn test_request_round_trip() {
+        let mut req = HttpRequestMessage::empty();
+        req.method = HttpMethod::Post;
+        req.url = String::from("/form");
+        req.headers.append("Host", "example.com");
+        req.body = b"ssid=x".to_vec();
+
+        let bytes = req.to_bytes();
+        assert_eq!(&bytes[..], &b"POST /form HTTP/1.1\r\nHost: example.com\r\nContent-Length: 6\r\n\r\nssid=x"[..]);
+
+        let mut parser = HttpRequestParser::new();
+        match parser.parse_bytes(&bytes).unwrap() {
+            HttpRequestParserState::Complete => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+        assert_eq!(&parser.get_request().body[..], b"ssid=x");
+        assert_eq!(parser.get_request().http_version, "1.1");
+
+        // the framing always matches the body
+        req.headers.insert("Content-Length", "99");
+        assert_eq!(&req.to_bytes()[..], &b"POST /form HTTP/1.1\r\nHost: example.com\r\nContent-Length: 6\r\n\r\nssid=x"[..]);
+
+        req.headers.remove("Content-Length");
+        req.headers.insert("Transfer-Encoding", "chunked");
+        req.trailers.insert("Checksum", "abc");
+        let bytes = req.to_bytes();
+        assert_eq!(&bytes[..], &b"POST /form HTTP/1.1\r\nHost: example.com\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nssid=x\r\n0\r\nChecksum: abc\r\n\r\n"[..]);
+        let mut parser = HttpRequestParser::new();
+        match parser.parse_bytes(&bytes).unwrap() {
+            HttpRequestParserState::Complete => (),
+            s => panic!("expected a complete request, got {:?}", s)
+        }
+        assert_eq!(&parser.get_request().body[..], b"ssid=x");
+        assert_eq!(parser.get_request().trailers.get("Checksum").unwrap(), "abc");
+
+        let mut req = HttpRequestMessage::empty();
+        req.method = HttpMethod::Post;
+        req.url = String::from("/empty");
+        assert_eq!(&req.to_bytes()[..], &b"POST /empty HTTP/1.1\r\nContent-Length: 0\r\n\r\n"[..]);
+        req.method = HttpMethod::Get;
+        assert_eq!(&req.to_bytes()[..], &b"GET /empty HTTP/1.1\r\n\r\n"[..]);
+    }
+}
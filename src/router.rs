@@ -1,4 +1,6 @@
 use http::*;
+use uri::*;
+use status::StatusCode;
 use collections::vec::*;
 use collections::String;
 use collections::string::ToString;
@@ -10,11 +12,29 @@ pub enum HttpRouteError {
     NotApplicable,
     ProcessingError,
     NoRouteFound,
+    /// A route matches the URL, but not the method. Lists the methods that would have matched.
+    MethodNotAllowed(Vec<HttpMethod>),
+}
+
+impl HttpRouteError {
+    /// The status to respond with when routing or a route fails.
+    pub fn status_code(&self) -> StatusCode {
+        match *self {
+            HttpRouteError::NotApplicable | HttpRouteError::NoRouteFound => StatusCode::NotFound,
+            HttpRouteError::MethodNotAllowed(_) => StatusCode::MethodNotAllowed,
+            HttpRouteError::ProcessingError => StatusCode::InternalServerError,
+        }
+    }
 }
 
 pub trait HttpRoute {
     fn try(&self, msg: &HttpRequestMessage) -> Result<bool, HttpRouteError>;
     fn execute(&self, msg: &HttpRequestMessage) -> Result<HttpResponseMessage, HttpRouteError>;
+
+    /// Methods this route accepts for the request's URL, empty if the URL doesn't match.
+    fn allowed_methods(&self, _msg: &HttpRequestMessage) -> Vec<HttpMethod> {
+        Vec::new()
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -83,65 +103,62 @@ impl DynamicUrl {
     }
 
     pub fn match_url(&self, url: &str) -> Option<DynamicUrlMatch> {
-        let mut is_match = true;
+        if !url.starts_with("/") {
+            return None;
+        }
+
+        let segments: Vec<String> = url[1..].split('/').map(|s| s.to_string()).collect();
+        self.match_segments(&segments)
+    }
+
+    /// Match the decoded path segments of a request, see `Uri::segments`. A variable
+    /// takes the rest of its segment, including a `/` that was sent encoded.
+    pub fn match_segments(&self, segments: &[String]) -> Option<DynamicUrlMatch> {
         let mut url_match = DynamicUrlMatch {
             vars: BTreeMap::new()
         };
 
-        let mut u = url;
+        // patterns start with the `/` in front of the first segment, so begin in an empty one before it
+        let count = segments.len() + 1;
         let mut i = 0;
-        loop {
-            let part = &self.parts[i];
+        let mut pos = 0;
 
+        for part in &self.parts {
             match *part {
                 DynamicUrlPart::Static(ref m_url) => {
-                    if u.starts_with(m_url) {
-                        u = &u[m_url.len()..];
-                    } else {
-                        is_match = false;
-                        break;
+                    for (n, piece) in m_url.split('/').enumerate() {
+                        if n > 0 {
+                            // the current segment has to end at a `/` of the pattern
+                            if pos != segment(segments, i).len() || i + 1 == count {
+                                return None;
+                            }
+                            i += 1;
+                            pos = 0;
+                        }
+
+                        if !segment(segments, i)[pos..].starts_with(piece) {
+                            return None;
+                        }
+                        pos += piece.len();
                     }
                 },
                 DynamicUrlPart::UrlVar(ref var_name) => {
-                    if u.len() == 0 {
-                        is_match = false;
-                        break;
-                    } else {
-                        let m = u.splitn(1, "/").collect::<Vec<_>>();
-                        if m.len() == 2 {
-                            url_match.vars.insert(var_name.clone(), m[0].to_string());
-                            u = m[1];
-                        } else if u.ends_with("/") {
-                            url_match.vars.insert(var_name.clone(), u[..(u.len()-1)].to_string());
-                            u = &u[..(u.len()-1)];
-                        } else if i == self.parts.len() - 1 {
-                            // we're the last one, grab everything if possible
-                            let sep = u.find("/");
-                            if sep.is_some() {
-                                is_match = false;
-                                break;
-                            } else if u.len() > 0 {
-                                url_match.vars.insert(var_name.clone(), u.to_string());
-                            } else {
-                                is_match = false;
-                                break;
-                            }
-                        } else if u.len() == 0 {
-                            is_match = false;
-                            break;
-                        }
+                    let rest = &segment(segments, i)[pos..];
+                    if rest.len() == 0 {
+                        return None;
                     }
+                    url_match.vars.insert(var_name.clone(), rest.to_string());
+                    pos += rest.len();
                 }
             }
-
-            i += 1;
-            if i == self.parts.len() { break; }
         }
 
-        // todo: check if all required variables are non-empty!
-        // todo: url decode vars
+        if pos != segment(segments, i).len() {
+            return None;
+        }
 
-        if is_match {
+        // a trailing slash is ignored
+        if i + 1 == count || (i + 2 == count && segment(segments, i + 1).len() == 0) {
             Some(url_match)
         } else {
             None
@@ -149,6 +166,11 @@ impl DynamicUrl {
     }
 }
 
+/// Segment `i` of a path, counting the empty one in front of its leading `/` as the first.
+fn segment(segments: &[String], i: usize) -> &str {
+    if i == 0 { "" } else { &segments[i - 1] }
+}
+
 #[derive(Debug)]
 pub struct DynamicUrlMatch {
     vars: BTreeMap<String, String>,
@@ -176,11 +198,15 @@ impl HttpRouteDynamicUrl {
                 action: Box::new(action)
             }
         }
+
+    fn match_request(&self, msg: &HttpRequestMessage) -> Option<DynamicUrlMatch> {
+        on_segments(msg, |segments| self.url.match_segments(segments)).and_then(|m| m)
+    }
 }
 
 impl HttpRoute for HttpRouteDynamicUrl {
     fn try(&self, msg: &HttpRequestMessage) -> Result<bool, HttpRouteError> {
-        let m = self.url.match_url(&msg.url);
+        let m = self.match_request(msg);
         if m.is_some() && self.methods.contains(&msg.method) {
             return Ok(true);
         }
@@ -189,7 +215,7 @@ impl HttpRoute for HttpRouteDynamicUrl {
     }
 
     fn execute(&self, msg: &HttpRequestMessage) -> Result<HttpResponseMessage, HttpRouteError> {
-        let m = self.url.match_url(&msg.url);
+        let m = self.match_request(msg);
 
         if m.is_some() {
             Ok((self.action)(msg, &m.unwrap()))
@@ -197,6 +223,14 @@ impl HttpRoute for HttpRouteDynamicUrl {
             Err(HttpRouteError::NoRouteFound)
         }
     }
+
+    fn allowed_methods(&self, msg: &HttpRequestMessage) -> Vec<HttpMethod> {
+        if self.match_request(msg).is_some() {
+            return self.methods.clone();
+        }
+
+        Vec::new()
+    }
 }
 
 pub struct HttpRouteStaticUrl {
@@ -206,20 +240,30 @@ pub struct HttpRouteStaticUrl {
 }
 
 impl HttpRouteStaticUrl {
-    pub fn new_get<F>(url: &str, action: F) -> HttpRouteStaticUrl 
+    pub fn new<F>(url: &str, method: HttpMethod, action: F) -> HttpRouteStaticUrl
         where F: Fn(&HttpRequestMessage) -> HttpResponseMessage + Send + Sync + 'static
         {
             HttpRouteStaticUrl {
                 urls: vec![url.to_string()],
-                methods: vec![HttpMethod::Get],
+                methods: vec![method],
                 action: Box::new(action)
             }
         }
+
+    pub fn new_get<F>(url: &str, action: F) -> HttpRouteStaticUrl 
+        where F: Fn(&HttpRequestMessage) -> HttpResponseMessage + Send + Sync + 'static
+        {
+            HttpRouteStaticUrl::new(url, HttpMethod::Get, action)
+        }
+
+    fn matches_url(&self, msg: &HttpRequestMessage) -> bool {
+        on_segments(msg, |segments| self.urls.iter().any(|url| path_matches(url, segments))).unwrap_or(false)
+    }
 }
 
 impl HttpRoute for HttpRouteStaticUrl {
     fn try(&self, msg: &HttpRequestMessage) -> Result<bool, HttpRouteError> {
-        if self.urls.contains(&msg.url) && self.methods.contains(&msg.method) {
+        if self.matches_url(msg) && self.methods.contains(&msg.method) {
             return Ok(true);
         }
 
@@ -230,6 +274,40 @@ impl HttpRoute for HttpRouteStaticUrl {
         //Err(HttpRouteError::ProcessingError)
         Ok((self.action)(msg))
     }
+
+    fn allowed_methods(&self, msg: &HttpRequestMessage) -> Vec<HttpMethod> {
+        if self.matches_url(msg) {
+            return self.methods.clone();
+        }
+
+        Vec::new()
+    }
+}
+
+/// Run `f` on the decoded path segments routes are matched against, parsing `url` only
+/// for requests that didn't come from the parser. `None` if the target doesn't parse.
+fn on_segments<R, F: FnOnce(&[String]) -> R>(msg: &HttpRequestMessage, f: F) -> Option<R> {
+    match msg.uri {
+        Some(ref uri) => Some(f(&uri.segments)),
+        None => Uri::parse(&msg.url).ok().map(|uri| f(&uri.segments))
+    }
+}
+
+/// Whether the literal path `url` consists of exactly these segments.
+fn path_matches(url: &str, segments: &[String]) -> bool {
+    if !url.starts_with("/") {
+        return false;
+    }
+
+    let mut i = 0;
+    for s in url[1..].split('/') {
+        if i == segments.len() || segments[i] != s {
+            return false;
+        }
+        i += 1;
+    }
+
+    i == segments.len()
 }
 
 pub fn http_router<'a>(routes: &'a [Box<HttpRoute + Send + Sync + 'static>], req: &HttpRequestMessage) -> Result<&'a Box<HttpRoute + Send + Sync + 'static>, HttpRouteError> {
@@ -240,12 +318,42 @@ pub fn http_router<'a>(routes: &'a [Box<HttpRoute + Send + Sync + 'static>], req
         }
     }
 
+    let mut allowed = Vec::new();
+    for route in routes {
+        let methods = route.allowed_methods(&req);
+
+        // HEAD is answered like GET, the body is left out when the response is written
+        if req.method == HttpMethod::Head && methods.contains(&HttpMethod::Get) {
+            return Ok(route);
+        }
+
+        for m in methods {
+            let is_get = m == HttpMethod::Get;
+            if !allowed.contains(&m) {
+                allowed.push(m);
+            }
+            if is_get && !allowed.contains(&HttpMethod::Head) {
+                allowed.push(HttpMethod::Head);
+            }
+        }
+    }
+
+    if allowed.len() > 0 {
+        return Err(HttpRouteError::MethodNotAllowed(allowed));
+    }
+
     return Err(HttpRouteError::NoRouteFound);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use http::*;
+    use uri::*;
+    use status::StatusCode;
+    use alloc::boxed::Box;
+    use collections::vec::Vec;
+    use collections::String;
 
     use collections::string::ToString;
 
@@ -283,4 +391,93 @@ mod tests {
             println!("url match: {:?}", m);
         }
     }
+
+    #[test]
+    pub fn test_extension_method_routing() {
+        let routes: Vec<Box<HttpRoute + Send + Sync + 'static>> = vec![
+            Box::new(HttpRouteStaticUrl::new("/dav", HttpMethod::Extension(String::from("PROPFIND")), |_| {
+                HttpResponseMessage::html_utf8("props")
+            }))
+        ];
+
+        let mut req = HttpRequestMessage::empty();
+        req.url = String::from("/dav");
+        assert!(http_router(&routes, &req).is_err());
+
+        req.method = HttpMethod::Extension(String::from("PROPFIND"));
+        assert!(http_router(&routes, &req).is_ok());
+    }
+
+    #[test]
+    pub fn test_method_not_allowed() {
+        let routes: Vec<Box<HttpRoute + Send + Sync + 'static>> = vec![
+            Box::new(HttpRouteStaticUrl::new_get("/form", |_| HttpResponseMessage::html_utf8("form"))),
+            Box::new(HttpRouteStaticUrl::new("/form", HttpMethod::Post, |_| HttpResponseMessage::html_utf8("posted"))),
+            Box::new(HttpRouteDynamicUrl::new(DynamicUrl::parse_str("/test/:id").unwrap(), HttpMethod::Get, |_, _| {
+                HttpResponseMessage::html_utf8("test")
+            }))
+        ];
+
+        let mut req = HttpRequestMessage::empty();
+        req.method = HttpMethod::Delete;
+        req.url = String::from("/form");
+        match http_router(&routes, &req) {
+            Err(e) => {
+                assert_eq!(e, HttpRouteError::MethodNotAllowed(vec![HttpMethod::Get, HttpMethod::Head, HttpMethod::Post]));
+                assert_eq!(e.status_code(), StatusCode::MethodNotAllowed);
+            },
+            Ok(_) => panic!("DELETE shouldn't be routed")
+        }
+
+        req.url = String::from("/test/5");
+        assert_eq!(http_router(&routes, &req).err(), Some(HttpRouteError::MethodNotAllowed(vec![HttpMethod::Get, HttpMethod::Head])));
+
+        // HEAD falls back to the GET route
+        req.method = HttpMethod::Head;
+        let resp = http_router(&routes, &req).unwrap().execute(&req).unwrap();
+        assert_eq!(&resp.body[..], b"test");
+        req.url = String::from("/form");
+        let resp = http_router(&routes, &req).unwrap().execute(&req).unwrap();
+        assert_eq!(&resp.body[..], b"form");
+
+        req.url = String::from("/nothing");
+        assert_eq!(http_router(&routes, &req).err().unwrap().status_code(), StatusCode::NotFound);
+    }
+
+    #[test]
+    pub fn test_routing_on_path() {
+        let routes: Vec<Box<HttpRoute + Send + Sync + 'static>> = vec![
+            Box::new(HttpRouteStaticUrl::new_get("/form", |_| HttpResponseMessage::html_utf8("form"))),
+            Box::new(HttpRouteDynamicUrl::new(DynamicUrl::parse_str("/test/:id").unwrap(), HttpMethod::Get, |_, vars| {
+                HttpResponseMessage::html_utf8(vars.get("id").unwrap())
+            }))
+        ];
+
+        let mut req = HttpRequestMessage::empty();
+        for url in vec!["/form?ssid=x", "/form#top", "/%66orm", "http://example.com/form?x"] {
+            req.url = String::from(url);
+            assert!(http_router(&routes, &req).is_ok(), "{} should be routed", url);
+        }
+
+        req.url = String::from("/test/a%20b?x=1");
+        let resp = http_router(&routes, &req).unwrap().execute(&req).unwrap();
+        assert_eq!(&resp.body[..], b"a b");
+
+        req.url = String::from("/form%3Fssid=x");
+        assert!(http_router(&routes, &req).is_err());
+
+        // an encoded slash doesn't split a segment
+        req.url = String::from("/test/a%2Fb");
+        let resp = http_router(&routes, &req).unwrap().execute(&req).unwrap();
+        assert_eq!(&resp.body[..], b"a/b");
+        req.url = String::from("/test/a/b");
+        assert!(http_router(&routes, &req).is_err());
+        req.url = String::from("/test/5/");
+        assert!(http_router(&routes, &req).is_ok());
+
+        // the parsed target is used when there is one
+        req.url = String::from("/nothing");
+        req.uri = Uri::parse("/form").ok();
+        assert!(http_router(&routes, &req).is_ok());
+    }
 }
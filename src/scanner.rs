@@ -0,0 +1,86 @@
+use parser::LineEndingPolicy;
+use collections::vec::*;
+
+/// Where the scanner is, carried over between calls.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ScanState {
+    InLine,
+    /// The last byte seen was a CR, a LF next ends the line.
+    AfterCr,
+}
+
+/// Outcome of `LineScanner::scan`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ScanResult {
+    /// `line` holds a complete line, after this many bytes.
+    Line(usize),
+    /// All of the data went into the line in progress.
+    Partial,
+    /// A CR not followed by LF, or a bare LF the policy doesn't allow.
+    BareLineEnding,
+}
+
+/// Splits the head of a request or response into lines as the bytes arrive,
+/// keeping only the line in progress. Shared by both parsers so they agree on
+/// what ends a line.
+pub struct LineScanner {
+    /// The line being received, without its line ending.
+    pub line: Vec<u8>,
+    pub state: ScanState,
+    pub line_endings: LineEndingPolicy,
+}
+
+impl LineScanner {
+    pub fn new() -> LineScanner {
+        LineScanner {
+            line: Vec::new(),
+            state: ScanState::InLine,
+            line_endings: LineEndingPolicy::Strict
+        }
+    }
+
+    /// Forget the line in progress, the policy is kept.
+    pub fn reset(&mut self) {
+        self.line.clear();
+        self.state = ScanState::InLine;
+    }
+
+    /// Append `data` to the line in progress, up to the end of the line. Once a line
+    /// is returned, clear `line` before scanning the rest.
+    pub fn scan(&mut self, data: &[u8]) -> ScanResult {
+        if data.len() == 0 {
+            return ScanResult::Partial;
+        }
+
+        if self.state == ScanState::AfterCr {
+            self.state = ScanState::InLine;
+            if data[0] != b'\n' {
+                return ScanResult::BareLineEnding;
+            }
+            return ScanResult::Line(1);
+        }
+
+        match data.iter().position(|&b| b == b'\r' || b == b'\n') {
+            Some(n) => {
+                self.line.push_all(&data[..n]);
+                if data[n] == b'\n' {
+                    if self.line_endings == LineEndingPolicy::AllowBareLf {
+                        return ScanResult::Line(n + 1);
+                    }
+                    return ScanResult::BareLineEnding;
+                }
+
+                // the LF may be right behind it, or arrive with the next data
+                self.state = ScanState::AfterCr;
+                match self.scan(&data[(n + 1)..]) {
+                    ScanResult::Line(m) => ScanResult::Line(n + 1 + m),
+                    r => r
+                }
+            },
+            None => {
+                self.line.push_all(data);
+                ScanResult::Partial
+            }
+        }
+    }
+}
@@ -0,0 +1,377 @@
+use std::io;
+use std::io::{Read, Write, ErrorKind};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs, SocketAddr, Shutdown};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use http::*;
+use parser::*;
+use router::*;
+use status::StatusCode;
+use transport::*;
+use collections::vec::*;
+use collections::BTreeMap;
+use alloc::boxed::Box;
+
+/// A blocking HTTP/1.1 server on top of `std::net`, serving connections from a fixed pool of threads.
+///
+/// ```ignore
+/// let server = HttpServer::new(routes).threads(4).bind("127.0.0.1:8088").unwrap();
+/// let shutdown = server.shutdown_handle();
+/// server.serve().unwrap();
+/// ```
+pub struct HttpServer {
+    routes: Vec<Box<HttpRoute + Send + Sync + 'static>>,
+    error_page: Option<Box<ErrorPageFn>>,
+    limits: ParserLimits,
+    line_endings: LineEndingPolicy,
+    threads: usize,
+    keep_alive_timeout: Duration,
+    read_timeout: Duration,
+    write_timeout: Duration,
+}
+
+impl HttpServer {
+    pub fn new(routes: Vec<Box<HttpRoute + Send + Sync + 'static>>) -> HttpServer {
+        HttpServer {
+            routes: routes,
+            error_page: None,
+            limits: ParserLimits::default(),
+            line_endings: LineEndingPolicy::Strict,
+            threads: 4,
+            keep_alive_timeout: Duration::from_secs(5),
+            read_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(30)
+        }
+    }
+
+    /// Number of connections served at the same time. Further connections wait to be picked up.
+    pub fn threads(mut self, threads: usize) -> HttpServer {
+        self.threads = if threads == 0 { 1 } else { threads };
+        self
+    }
+
+    /// How long a persistent connection may sit idle between requests.
+    pub fn keep_alive_timeout(mut self, timeout: Duration) -> HttpServer {
+        self.keep_alive_timeout = timeout;
+        self
+    }
+
+    /// How long to wait for more of a request once it has started arriving.
+    pub fn read_timeout(mut self, timeout: Duration) -> HttpServer {
+        self.read_timeout = timeout;
+        self
+    }
+
+    pub fn write_timeout(mut self, timeout: Duration) -> HttpServer {
+        self.write_timeout = timeout;
+        self
+    }
+
+    pub fn limits(mut self, limits: ParserLimits) -> HttpServer {
+        self.limits = limits;
+        self
+    }
+
+    pub fn line_endings(mut self, policy: LineEndingPolicy) -> HttpServer {
+        self.line_endings = policy;
+        self
+    }
+
+    /// Replace the default error pages.
+    pub fn error_page<F>(mut self, page: F) -> HttpServer
+        where F: Fn(StatusCode, Option<&HttpRequestMessage>) -> HttpResponseMessage + Send + Sync + 'static
+    {
+        self.error_page = Some(Box::new(page));
+        self
+    }
+
+    pub fn bind<A: ToSocketAddrs>(self, addr: A) -> io::Result<BoundHttpServer> {
+        let listener = try!(TcpListener::bind(addr));
+        let local_addr = try!(listener.local_addr());
+
+        Ok(BoundHttpServer {
+            listener: listener,
+            shutdown: ShutdownHandle {
+                flag: Arc::new(AtomicBool::new(false)),
+                idle: Arc::new(Mutex::new(IdleConnections { next_id: 0, streams: BTreeMap::new() })),
+                addr: local_addr
+            },
+            server: Arc::new(self)
+        })
+    }
+
+    fn handle_client(&self, stream: TcpStream, shutdown: &ShutdownHandle) {
+        if stream.set_write_timeout(Some(self.write_timeout)).is_err() { return; }
+
+        let id = match shutdown.idle.lock() {
+            Ok(mut idle) => {
+                idle.next_id += 1;
+                idle.next_id
+            },
+            Err(_) => return
+        };
+
+        let mut transport = TcpTransport {
+            stream: stream,
+            id: id,
+            shutdown: shutdown.clone(),
+            keep_alive_timeout: self.keep_alive_timeout,
+            read_timeout: self.read_timeout
+        };
+        let options = ConnectionOptions {
+            limits: self.limits,
+            line_endings: self.line_endings,
+            error_page: self.error_page.as_ref().map(|p| &**p),
+            shutdown: Some(&shutdown.flag),
+            clock: None
+        };
+
+        let _ = serve_connection(&mut transport, &self.routes, &options);
+    }
+}
+
+/// `Transport` over a socket, switching between the idle and the read timeout.
+/// While idle, the socket is registered with the shutdown handle to be closed early.
+struct TcpTransport {
+    stream: TcpStream,
+    id: usize,
+    shutdown: ShutdownHandle,
+    keep_alive_timeout: Duration,
+    read_timeout: Duration,
+}
+
+impl TcpTransport {
+    fn set_idle(&self, idle: bool) {
+        let mut connections = match self.shutdown.idle.lock() {
+            Ok(c) => c,
+            Err(_) => return
+        };
+
+        if !idle {
+            connections.streams.remove(&self.id);
+        } else if self.shutdown.is_shutdown() {
+            // shut down after the handle went through the idle connections, don't wait for the timeout
+            let _ = self.stream.shutdown(Shutdown::Read);
+        } else if let Ok(s) = self.stream.try_clone() {
+            connections.streams.insert(self.id, s);
+        }
+    }
+}
+
+impl Transport for TcpTransport {
+    type Error = io::Error;
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.stream.write_all(data)
+    }
+
+    fn close(&mut self) {
+        self.set_idle(false);
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+
+    fn waiting_for_request(&mut self) {
+        let _ = self.stream.set_read_timeout(Some(self.keep_alive_timeout));
+        self.set_idle(true);
+    }
+
+    fn receiving_request(&mut self) {
+        self.set_idle(false);
+        let _ = self.stream.set_read_timeout(Some(self.read_timeout));
+    }
+}
+
+/// A server with a listening socket, ready to `serve`.
+pub struct BoundHttpServer {
+    listener: TcpListener,
+    shutdown: ShutdownHandle,
+    server: Arc<HttpServer>,
+}
+
+impl BoundHttpServer {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.shutdown.addr
+    }
+
+    /// A handle for stopping `serve` from another thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Accept and serve connections until shut down. Requests in progress are
+    /// completed before this returns, idle persistent connections are closed.
+    pub fn serve(self) -> io::Result<()> {
+        let (sender, receiver) = channel::<TcpStream>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let mut workers = Vec::with_capacity(self.server.threads);
+        for _ in 0..self.server.threads {
+            let server = self.server.clone();
+            let receiver = receiver.clone();
+            let shutdown = self.shutdown.clone();
+            workers.push(thread::spawn(move || worker(&server, &receiver, &shutdown)));
+        }
+
+        let mut result = Ok(());
+        for stream in self.listener.incoming() {
+            if self.shutdown.is_shutdown() {
+                break;
+            }
+
+            match stream {
+                Ok(stream) => {
+                    if sender.send(stream).is_err() { break; }
+                },
+                // the client gave up before the connection was accepted
+                Err(ref e) if e.kind() == ErrorKind::ConnectionAborted || e.kind() == ErrorKind::ConnectionReset => (),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        // no more connections, the workers exit once the queue is drained
+        drop(sender);
+        for w in workers {
+            let _ = w.join();
+        }
+
+        result
+    }
+}
+
+fn worker(server: &HttpServer, receiver: &Mutex<Receiver<TcpStream>>, shutdown: &ShutdownHandle) {
+    loop {
+        let stream = match receiver.lock() {
+            Ok(r) => r.recv(),
+            Err(_) => return
+        };
+
+        match stream {
+            Ok(stream) => {
+                if shutdown.is_shutdown() {
+                    let _ = stream.shutdown(Shutdown::Both);
+                } else {
+                    server.handle_client(stream, shutdown);
+                }
+            },
+            Err(_) => return
+        }
+    }
+}
+
+/// Persistent connections waiting for their next request, by connection id.
+struct IdleConnections {
+    next_id: usize,
+    streams: BTreeMap<usize, TcpStream>,
+}
+
+/// Stops a running `BoundHttpServer`.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    flag: Arc<AtomicBool>,
+    idle: Arc<Mutex<IdleConnections>>,
+    addr: SocketAddr,
+}
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+
+        // idle connections see the end of the stream, instead of waiting out the keep-alive timeout
+        if let Ok(idle) = self.idle.lock() {
+            for (_, stream) in idle.streams.iter() {
+                let _ = stream.shutdown(Shutdown::Read);
+            }
+        }
+
+        // wake up the accept loop
+        let mut addr = self.addr;
+        let unspecified = match addr {
+            SocketAddr::V4(a) => a.ip().is_unspecified(),
+            SocketAddr::V6(a) => a.ip().is_unspecified()
+        };
+        if unspecified {
+            addr = SocketAddr::new("127.0.0.1".parse().unwrap(), addr.port());
+        }
+        let _ = TcpStream::connect(addr);
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::*;
+    use router::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use std::string::String;
+
+    #[test]
+    pub fn test_serve_and_shutdown() {
+        let server = HttpServer::new(vec![
+                Box::new(HttpRouteStaticUrl::new_get("/", |_| HttpResponseMessage::html_utf8("hello")))
+            ])
+            .threads(2)
+            .bind("127.0.0.1:0")
+            .unwrap();
+
+        let addr = server.local_addr();
+        let shutdown = server.shutdown_handle();
+        let t = thread::spawn(move || server.serve());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\nGET /missing HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n").unwrap();
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).unwrap();
+
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(resp.contains("hello"));
+        assert!(resp.contains("HTTP/1.1 404 Not Found\r\n"));
+
+        shutdown.shutdown();
+        assert!(t.join().unwrap().is_ok());
+    }
+
+    #[test]
+    pub fn test_shutdown_closes_idle_connections() {
+        let server = HttpServer::new(vec![
+                Box::new(HttpRouteStaticUrl::new_get("/", |_| HttpResponseMessage::html_utf8("hello")))
+            ])
+            .keep_alive_timeout(Duration::from_secs(60))
+            .bind("127.0.0.1:0")
+            .unwrap();
+
+        let addr = server.local_addr();
+        let shutdown = server.shutdown_handle();
+        let t = thread::spawn(move || server.serve());
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+        let mut buf = [0; 1024];
+        let n = stream.read(&mut buf).unwrap();
+        assert!(buf[..n].starts_with(b"HTTP/1.1 200 OK\r\n"));
+
+        // the connection is kept open for another request, shutting down mustn't wait a minute for it
+        let start = Instant::now();
+        shutdown.shutdown();
+        assert!(t.join().unwrap().is_ok());
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+    }
+}
@@ -0,0 +1,336 @@
+use core::fmt;
+
+/// HTTP status code, with a variant for every code in the IANA registry.
+///
+/// Codes that aren't registered are kept as `Unregistered`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StatusCode {
+    Continue,
+    SwitchingProtocols,
+    Processing,
+    EarlyHints,
+    Ok,
+    Created,
+    Accepted,
+    NonAuthoritativeInformation,
+    NoContent,
+    ResetContent,
+    PartialContent,
+    MultiStatus,
+    AlreadyReported,
+    ImUsed,
+    MultipleChoices,
+    MovedPermanently,
+    Found,
+    SeeOther,
+    NotModified,
+    UseProxy,
+    TemporaryRedirect,
+    PermanentRedirect,
+    BadRequest,
+    Unauthorized,
+    PaymentRequired,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    NotAcceptable,
+    ProxyAuthenticationRequired,
+    RequestTimeout,
+    Conflict,
+    Gone,
+    LengthRequired,
+    PreconditionFailed,
+    PayloadTooLarge,
+    UriTooLong,
+    UnsupportedMediaType,
+    RangeNotSatisfiable,
+    ExpectationFailed,
+    MisdirectedRequest,
+    UnprocessableEntity,
+    Locked,
+    FailedDependency,
+    TooEarly,
+    UpgradeRequired,
+    PreconditionRequired,
+    TooManyRequests,
+    RequestHeaderFieldsTooLarge,
+    UnavailableForLegalReasons,
+    InternalServerError,
+    NotImplemented,
+    BadGateway,
+    ServiceUnavailable,
+    GatewayTimeout,
+    HttpVersionNotSupported,
+    VariantAlsoNegotiates,
+    InsufficientStorage,
+    LoopDetected,
+    NotExtended,
+    NetworkAuthenticationRequired,
+    Unregistered(u16),
+}
+
+impl StatusCode {
+    pub fn from_u16(code: u16) -> StatusCode {
+        match code {
+            100 => StatusCode::Continue,
+            101 => StatusCode::SwitchingProtocols,
+            102 => StatusCode::Processing,
+            103 => StatusCode::EarlyHints,
+            200 => StatusCode::Ok,
+            201 => StatusCode::Created,
+            202 => StatusCode::Accepted,
+            203 => StatusCode::NonAuthoritativeInformation,
+            204 => StatusCode::NoContent,
+            205 => StatusCode::ResetContent,
+            206 => StatusCode::PartialContent,
+            207 => StatusCode::MultiStatus,
+            208 => StatusCode::AlreadyReported,
+            226 => StatusCode::ImUsed,
+            300 => StatusCode::MultipleChoices,
+            301 => StatusCode::MovedPermanently,
+            302 => StatusCode::Found,
+            303 => StatusCode::SeeOther,
+            304 => StatusCode::NotModified,
+            305 => StatusCode::UseProxy,
+            307 => StatusCode::TemporaryRedirect,
+            308 => StatusCode::PermanentRedirect,
+            400 => StatusCode::BadRequest,
+            401 => StatusCode::Unauthorized,
+            402 => StatusCode::PaymentRequired,
+            403 => StatusCode::Forbidden,
+            404 => StatusCode::NotFound,
+            405 => StatusCode::MethodNotAllowed,
+            406 => StatusCode::NotAcceptable,
+            407 => StatusCode::ProxyAuthenticationRequired,
+            408 => StatusCode::RequestTimeout,
+            409 => StatusCode::Conflict,
+            410 => StatusCode::Gone,
+            411 => StatusCode::LengthRequired,
+            412 => StatusCode::PreconditionFailed,
+            413 => StatusCode::PayloadTooLarge,
+            414 => StatusCode::UriTooLong,
+            415 => StatusCode::UnsupportedMediaType,
+            416 => StatusCode::RangeNotSatisfiable,
+            417 => StatusCode::ExpectationFailed,
+            421 => StatusCode::MisdirectedRequest,
+            422 => StatusCode::UnprocessableEntity,
+            423 => StatusCode::Locked,
+            424 => StatusCode::FailedDependency,
+            425 => StatusCode::TooEarly,
+            426 => StatusCode::UpgradeRequired,
+            428 => StatusCode::PreconditionRequired,
+            429 => StatusCode::TooManyRequests,
+            431 => StatusCode::RequestHeaderFieldsTooLarge,
+            451 => StatusCode::UnavailableForLegalReasons,
+            500 => StatusCode::InternalServerError,
+            501 => StatusCode::NotImplemented,
+            502 => StatusCode::BadGateway,
+            503 => StatusCode::ServiceUnavailable,
+            504 => StatusCode::GatewayTimeout,
+            505 => StatusCode::HttpVersionNotSupported,
+            506 => StatusCode::VariantAlsoNegotiates,
+            507 => StatusCode::InsufficientStorage,
+            508 => StatusCode::LoopDetected,
+            510 => StatusCode::NotExtended,
+            511 => StatusCode::NetworkAuthenticationRequired,
+            _ => StatusCode::Unregistered(code)
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        match *self {
+            StatusCode::Continue => 100,
+            StatusCode::SwitchingProtocols => 101,
+            StatusCode::Processing => 102,
+            StatusCode::EarlyHints => 103,
+            StatusCode::Ok => 200,
+            StatusCode::Created => 201,
+            StatusCode::Accepted => 202,
+            StatusCode::NonAuthoritativeInformation => 203,
+            StatusCode::NoContent => 204,
+            StatusCode::ResetContent => 205,
+            StatusCode::PartialContent => 206,
+            StatusCode::MultiStatus => 207,
+            StatusCode::AlreadyReported => 208,
+            StatusCode::ImUsed => 226,
+            StatusCode::MultipleChoices => 300,
+            StatusCode::MovedPermanently => 301,
+            StatusCode::Found => 302,
+            StatusCode::SeeOther => 303,
+            StatusCode::NotModified => 304,
+            StatusCode::UseProxy => 305,
+            StatusCode::TemporaryRedirect => 307,
+            StatusCode::PermanentRedirect => 308,
+            StatusCode::BadRequest => 400,
+            StatusCode::Unauthorized => 401,
+            StatusCode::PaymentRequired => 402,
+            StatusCode::Forbidden => 403,
+            StatusCode::NotFound => 404,
+            StatusCode::MethodNotAllowed => 405,
+            StatusCode::NotAcceptable => 406,
+            StatusCode::ProxyAuthenticationRequired => 407,
+            StatusCode::RequestTimeout => 408,
+            StatusCode::Conflict => 409,
+            StatusCode::Gone => 410,
+            StatusCode::LengthRequired => 411,
+            StatusCode::PreconditionFailed => 412,
+            StatusCode::PayloadTooLarge => 413,
+            StatusCode::UriTooLong => 414,
+            StatusCode::UnsupportedMediaType => 415,
+            StatusCode::RangeNotSatisfiable => 416,
+            StatusCode::ExpectationFailed => 417,
+            StatusCode::MisdirectedRequest => 421,
+            StatusCode::UnprocessableEntity => 422,
+            StatusCode::Locked => 423,
+            StatusCode::FailedDependency => 424,
+            StatusCode::TooEarly => 425,
+            StatusCode::UpgradeRequired => 426,
+            StatusCode::PreconditionRequired => 428,
+            StatusCode::TooManyRequests => 429,
+            StatusCode::RequestHeaderFieldsTooLarge => 431,
+            StatusCode::UnavailableForLegalReasons => 451,
+            StatusCode::InternalServerError => 500,
+            StatusCode::NotImplemented => 501,
+            StatusCode::BadGateway => 502,
+            StatusCode::ServiceUnavailable => 503,
+            StatusCode::GatewayTimeout => 504,
+            StatusCode::HttpVersionNotSupported => 505,
+            StatusCode::VariantAlsoNegotiates => 506,
+            StatusCode::InsufficientStorage => 507,
+            StatusCode::LoopDetected => 508,
+            StatusCode::NotExtended => 510,
+            StatusCode::NetworkAuthenticationRequired => 511,
+            StatusCode::Unregistered(code) => code
+        }
+    }
+
+    /// The reason phrase recommended for this code, `None` for unregistered codes.
+    pub fn canonical_reason(&self) -> Option<&'static str> {
+        match *self {
+            StatusCode::Continue => Some("Continue"),
+            StatusCode::SwitchingProtocols => Some("Switching Protocols"),
+            StatusCode::Processing => Some("Processing"),
+            StatusCode::EarlyHints => Some("Early Hints"),
+            StatusCode::Ok => Some("OK"),
+            StatusCode::Created => Some("Created"),
+            StatusCode::Accepted => Some("Accepted"),
+            StatusCode::NonAuthoritativeInformation => Some("Non-Authoritative Information"),
+            StatusCode::NoContent => Some("No Content"),
+            StatusCode::ResetContent => Some("Reset Content"),
+            StatusCode::PartialContent => Some("Partial Content"),
+            StatusCode::MultiStatus => Some("Multi-Status"),
+            StatusCode::AlreadyReported => Some("Already Reported"),
+            StatusCode::ImUsed => Some("IM Used"),
+            StatusCode::MultipleChoices => Some("Multiple Choices"),
+            StatusCode::MovedPermanently => Some("Moved Permanently"),
+            StatusCode::Found => Some("Found"),
+            StatusCode::SeeOther => Some("See Other"),
+            StatusCode::NotModified => Some("Not Modified"),
+            StatusCode::UseProxy => Some("Use Proxy"),
+            StatusCode::TemporaryRedirect => Some("Temporary Redirect"),
+            StatusCode::PermanentRedirect => Some("Permanent Redirect"),
+            StatusCode::BadRequest => Some("Bad Request"),
+            StatusCode::Unauthorized => Some("Unauthorized"),
+            StatusCode::PaymentRequired => Some("Payment Required"),
+            StatusCode::Forbidden => Some("Forbidden"),
+            StatusCode::NotFound => Some("Not Found"),
+            StatusCode::MethodNotAllowed => Some("Method Not Allowed"),
+            StatusCode::NotAcceptable => Some("Not Acceptable"),
+            StatusCode::ProxyAuthenticationRequired => Some("Proxy Authentication Required"),
+            StatusCode::RequestTimeout => Some("Request Timeout"),
+            StatusCode::Conflict => Some("Conflict"),
+            StatusCode::Gone => Some("Gone"),
+            StatusCode::LengthRequired => Some("Length Required"),
+            StatusCode::PreconditionFailed => Some("Precondition Failed"),
+            StatusCode::PayloadTooLarge => Some("Payload Too Large"),
+            StatusCode::UriTooLong => Some("URI Too Long"),
+            StatusCode::UnsupportedMediaType => Some("Unsupported Media Type"),
+            StatusCode::RangeNotSatisfiable => Some("Range Not Satisfiable"),
+            StatusCode::ExpectationFailed => Some("Expectation Failed"),
+            StatusCode::MisdirectedRequest => Some("Misdirected Request"),
+            StatusCode::UnprocessableEntity => Some("Unprocessable Entity"),
+            StatusCode::Locked => Some("Locked"),
+            StatusCode::FailedDependency => Some("Failed Dependency"),
+            StatusCode::TooEarly => Some("Too Early"),
+            StatusCode::UpgradeRequired => Some("Upgrade Required"),
+            StatusCode::PreconditionRequired => Some("Precondition Required"),
+            StatusCode::TooManyRequests => Some("Too Many Requests"),
+            StatusCode::RequestHeaderFieldsTooLarge => Some("Request Header Fields Too Large"),
+            StatusCode::UnavailableForLegalReasons => Some("Unavailable For Legal Reasons"),
+            StatusCode::InternalServerError => Some("Internal Server Error"),
+            StatusCode::NotImplemented => Some("Not Implemented"),
+            StatusCode::BadGateway => Some("Bad Gateway"),
+            StatusCode::ServiceUnavailable => Some("Service Unavailable"),
+            StatusCode::GatewayTimeout => Some("Gateway Timeout"),
+            StatusCode::HttpVersionNotSupported => Some("HTTP Version Not Supported"),
+            StatusCode::VariantAlsoNegotiates => Some("Variant Also Negotiates"),
+            StatusCode::InsufficientStorage => Some("Insufficient Storage"),
+            StatusCode::LoopDetected => Some("Loop Detected"),
+            StatusCode::NotExtended => Some("Not Extended"),
+            StatusCode::NetworkAuthenticationRequired => Some("Network Authentication Required"),
+            StatusCode::Unregistered(_) => None
+        }
+    }
+
+    /// 1xx
+    pub fn is_informational(&self) -> bool {
+        self.class() == 1
+    }
+
+    /// 2xx
+    pub fn is_success(&self) -> bool {
+        self.class() == 2
+    }
+
+    /// 3xx
+    pub fn is_redirection(&self) -> bool {
+        self.class() == 3
+    }
+
+    /// 4xx
+    pub fn is_client_error(&self) -> bool {
+        self.class() == 4
+    }
+
+    /// 5xx
+    pub fn is_server_error(&self) -> bool {
+        self.class() == 5
+    }
+
+    fn class(&self) -> u16 {
+        self.to_u16() / 100
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_u16())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_status_codes() {
+        for code in 100..600 {
+            let s = StatusCode::from_u16(code);
+            assert_eq!(s.to_u16(), code);
+            assert_eq!(s.canonical_reason().is_some(), s != StatusCode::Unregistered(code));
+        }
+
+        assert_eq!(StatusCode::from_u16(404), StatusCode::NotFound);
+        assert_eq!(StatusCode::NotFound.canonical_reason(), Some("Not Found"));
+        assert_eq!(StatusCode::from_u16(299), StatusCode::Unregistered(299));
+        assert_eq!(format!("{}", StatusCode::Ok), "200");
+
+        assert!(StatusCode::Continue.is_informational());
+        assert!(StatusCode::NoContent.is_success());
+        assert!(StatusCode::Found.is_redirection());
+        assert!(StatusCode::NotFound.is_client_error());
+        assert!(StatusCode::BadGateway.is_server_error());
+        assert!(!StatusCode::Unregistered(299).is_client_error());
+        assert!(StatusCode::Unregistered(299).is_success());
+    }
+}
@@ -0,0 +1,246 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use http::*;
+use parser::*;
+use router::*;
+use status::StatusCode;
+use date::HttpClock;
+use collections::vec::*;
+use alloc::boxed::Box;
+
+/// A connection to a single client, provided by the network stack.
+///
+/// Implement this for whatever the board talks to the network through, be it
+/// a `std` socket, lwIP, smoltcp or a Wi-Fi module's AT command set, and let
+/// `serve_connection` run the HTTP side of it.
+pub trait Transport {
+    type Error;
+
+    /// Read whatever has arrived into `buf`, blocking until at least one byte
+    /// is available. `Ok(0)` means the client closed the connection.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+
+    /// Write the whole of `data`, blocking until the stack has accepted it.
+    fn write(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Close the connection. Called once, when `serve_connection` is done with it.
+    fn close(&mut self);
+
+    /// Called before waiting for the next request on a persistent connection,
+    /// e.g. to switch to the idle timeout.
+    fn waiting_for_request(&mut self) {}
+
+    /// Called once the first bytes of a request have arrived.
+    fn receiving_request(&mut self) {}
+}
+
+/// Builds the response sent for an error status, given the request if it could be parsed.
+pub type ErrorPageFn = Fn(StatusCode, Option<&HttpRequestMessage>) -> HttpResponseMessage + Send + Sync;
+
+/// Settings for `serve_connection`.
+#[derive(Default)]
+pub struct ConnectionOptions<'a> {
+    pub limits: ParserLimits,
+    pub line_endings: LineEndingPolicy,
+    /// Replaces the default error pages when set.
+    pub error_page: Option<&'a ErrorPageFn>,
+    /// When set, the connection is closed after the request in progress instead of waiting for another.
+    pub shutdown: Option<&'a AtomicBool>,
+    pub clock: Option<&'a HttpClock>,
+}
+
+/// Size of the buffer requests are read into.
+const READ_BUFFER_SIZE: usize = 4096;
+
+/// Size of the buffer responses are serialized through.
+const WRITE_BUFFER_SIZE: usize = 512;
+
+/// Serve requests on `transport` until the client closes the connection, either
+/// side asks for it to be closed, or a request can't be parsed. Each request is
+/// routed through `http_router`, failures are answered with an error page.
+///
+/// The connection is closed before returning, also on transport errors.
+pub fn serve_connection<T: Transport>(transport: &mut T, routes: &[Box<HttpRoute + Send + Sync + 'static>], options: &ConnectionOptions) -> Result<(), T::Error> {
+    let r = serve_requests(transport, routes, options);
+    transport.close();
+    r
+}
+
+fn serve_requests<T: Transport>(transport: &mut T, routes: &[Box<HttpRoute + Send + Sync + 'static>], options: &ConnectionOptions) -> Result<(), T::Error> {
+    let mut parser = HttpRequestParser::with_limits(options.limits);
+    parser.set_line_ending_policy(options.line_endings);
+    let mut buf = [0; READ_BUFFER_SIZE];
+
+    loop {
+        transport.waiting_for_request();
+
+        // a pipelined request may already be buffered from the previous read
+        let mut state = parser.parse_bytes(&[]);
+        let mut received = false;
+
+        loop {
+            match state {
+                Ok(HttpRequestParserState::Complete) => break,
+                Ok(HttpRequestParserState::MoreDataRequired) => (),
+                Err(e) => {
+                    let resp = error_response(options, e.status_code(), None);
+                    let serialize = SerializeOptions { keep_alive: Some(false), clock: options.clock, ..SerializeOptions::default() };
+                    return write_response(transport, &resp, &serialize);
+                }
+            }
+
+            let n = match transport.read(&mut buf) {
+                Ok(n) => n,
+                // nothing of a new request arrived, most likely the idle timeout
+                Err(_) if !received => return Ok(()),
+                Err(e) => return Err(e)
+            };
+            if n == 0 {
+                return Ok(());
+            }
+
+            if !received {
+                received = true;
+                transport.receiving_request();
+            }
+            state = parser.parse_bytes(&buf[..n]);
+        }
+
+        let req = parser.take_request().unwrap();
+
+        let resp = match http_router(routes, &req).and_then(|route| route.execute(&req)) {
+            Ok(resp) => resp,
+            Err(e) => route_error_response(options, &e, &req)
+        };
+
+        // finish the current request when shutting down, but don't wait for another one
+        let shutting_down = options.shutdown.map(|s| s.load(Ordering::SeqCst)).unwrap_or(false);
+        let keep_alive = req.keep_alive() && !resp.has_connection_token("close") && !shutting_down;
+        let serialize = SerializeOptions {
+            request_method: Some(&req.method),
+            request_version: Some(&req.http_version),
+            keep_alive: Some(keep_alive),
+            clock: options.clock
+        };
+        try!(write_response(transport, &resp, &serialize));
+
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+fn write_response<T: Transport>(transport: &mut T, resp: &HttpResponseMessage, options: &SerializeOptions) -> Result<(), T::Error> {
+    let mut serializer = resp.serializer(options);
+    let mut buf = [0; WRITE_BUFFER_SIZE];
+
+    while !serializer.is_done() {
+        let p = serializer.write_to(&mut buf);
+        try!(transport.write(&buf[..p.written]));
+    }
+
+    Ok(())
+}
+
+fn error_response(options: &ConnectionOptions, status: StatusCode, req: Option<&HttpRequestMessage>) -> HttpResponseMessage {
+    match options.error_page {
+        Some(page) => page(status, req),
+        None => default_error_page(status)
+    }
+}
+
+fn route_error_response(options: &ConnectionOptions, err: &HttpRouteError, req: &HttpRequestMessage) -> HttpResponseMessage {
+    let mut resp = error_response(options, err.status_code(), Some(req));
+
+    if let HttpRouteError::MethodNotAllowed(ref methods) = *err {
+        if !resp.headers.contains_key("Allow") {
+            let allow: Vec<&str> = methods.iter().map(|m| m.as_str()).collect();
+            resp.headers.insert("Allow", allow.join(", "));
+        }
+    }
+
+    resp
+}
+
+/// The page sent for an error status when no `error_page` is set.
+pub fn default_error_page(status: StatusCode) -> HttpResponseMessage {
+    HttpResponseMessage::builder()
+        .status(status)
+        .html(&format!("<h1>{} {}</h1>", status, status.canonical_reason().unwrap_or("Error")))
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::*;
+    use router::*;
+    use status::StatusCode;
+    use collections::vec::*;
+    use collections::String;
+    use alloc::boxed::Box;
+
+    /// Serves canned input and collects everything written.
+    struct MockTransport {
+        input: Vec<u8>,
+        pos: usize,
+        output: Vec<u8>,
+        closed: bool,
+    }
+
+    impl Transport for MockTransport {
+        type Error = ();
+
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+            if self.pos == self.input.len() { return Ok(0); }
+            let n = ::core::cmp::min(buf.len(), self.input.len() - self.pos);
+            for i in 0..n {
+                buf[i] = self.input[self.pos + i];
+            }
+            self.pos += n;
+            Ok(n)
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<(), ()> {
+            self.output.push_all(data);
+            Ok(())
+        }
+
+        fn close(&mut self) {
+            self.closed = true;
+        }
+    }
+
+    fn serve(input: &[u8], options: &ConnectionOptions) -> MockTransport {
+        let routes: Vec<Box<HttpRoute + Send + Sync + 'static>> = vec![
+            Box::new(HttpRouteStaticUrl::new_get("/", |_| HttpResponseMessage::html_utf8("hello")))
+        ];
+
+        let mut t = MockTransport { input: input.to_vec(), pos: 0, output: Vec::new(), closed: false };
+        assert!(serve_connection(&mut t, &routes, options).is_ok());
+        assert!(t.closed);
+        t
+    }
+
+    #[test]
+    pub fn test_serve_connection() {
+        let t = serve(b"GET / HTTP/1.1\r\n\r\nHEAD / HTTP/1.1\r\n\r\nDELETE / HTTP/1.1\r\n\r\nGET /missing HTTP/1.1\r\nConnection: close\r\n\r\nGET / HTTP/1.1\r\n\r\n", &ConnectionOptions::default());
+        let out = String::from_utf8(t.output).unwrap();
+
+        assert!(out.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(out.contains("hello"));
+        assert!(out.contains("HTTP/1.1 405 Method Not Allowed\r\n"));
+        assert!(out.contains("Allow: GET, HEAD\r\n"));
+        assert!(out.contains("HTTP/1.1 404 Not Found\r\n"));
+        // nothing is served after Connection: close
+        assert_eq!(out.matches("HTTP/1.1 ").count(), 4);
+        assert_eq!(out.matches("HTTP/1.1 200 OK\r\n").count(), 2);
+        assert_eq!(out.matches("hello").count(), 1);
+
+        let page = |status: StatusCode, _: Option<&HttpRequestMessage>| HttpResponseMessage::builder().status(status).text("oops").build();
+        let options = ConnectionOptions { error_page: Some(&page), ..ConnectionOptions::default() };
+        let t = serve(b"NOT A REQUEST\r\n\r\n", &options);
+        let out = String::from_utf8(t.output).unwrap();
+        assert!(out.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(out.ends_with("\r\n\r\noops"));
+    }
+}
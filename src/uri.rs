@@ -0,0 +1,192 @@
+use core::str::from_utf8;
+use http::*;
+use collections::vec::*;
+use collections::String;
+use collections::string::ToString;
+use collections::BTreeMap;
+
+/// The four shapes of a request-target, RFC 7230 section 5.3.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RequestTargetForm {
+    /// `/path?query`, what nearly all requests use.
+    Origin,
+    /// `http://host/path?query`, sent to proxies.
+    Absolute,
+    /// `host:port`, only for `CONNECT`.
+    Authority,
+    /// `*`, only for a server-wide `OPTIONS`.
+    Asterisk,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum UriError {
+    Empty,
+    /// Not one of the request-target forms.
+    InvalidForm,
+    /// A path segment doesn't decode to UTF-8.
+    InvalidPath,
+}
+
+/// A parsed request-target.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Uri {
+    pub form: RequestTargetForm,
+    /// Only for the absolute form, e.g. `http`.
+    pub scheme: Option<String>,
+    /// `host[:port]` of the absolute and authority forms.
+    pub authority: Option<String>,
+    /// The path as sent, still percent-encoded. Empty for the authority form, `*` for the asterisk form.
+    pub path: String,
+    /// The path split at `/`, each segment percent-decoded on its own so an encoded
+    /// `%2F` stays inside its segment. `/` is a single empty segment, a trailing
+    /// slash adds one. Empty for the authority and asterisk forms.
+    pub segments: Vec<String>,
+    /// The query without the `?`, still percent-encoded.
+    pub query: Option<String>,
+    /// The fragment without the `#`, still percent-encoded. Clients shouldn't send one.
+    pub fragment: Option<String>,
+}
+
+impl Uri {
+    pub fn parse(target: &str) -> Result<Uri, UriError> {
+        if target.len() == 0 {
+            return Err(UriError::Empty);
+        }
+
+        let mut uri = Uri {
+            form: RequestTargetForm::Origin,
+            scheme: None,
+            authority: None,
+            path: String::new(),
+            segments: Vec::new(),
+            query: None,
+            fragment: None
+        };
+
+        if target == "*" {
+            uri.form = RequestTargetForm::Asterisk;
+            uri.path = target.to_string();
+            return Ok(uri);
+        }
+
+        let mut rest = target;
+        if !rest.starts_with("/") {
+            let scheme_end = rest.find("://");
+            match scheme_end {
+                Some(i) if i > 0 && is_scheme(&rest[..i]) => {
+                    uri.form = RequestTargetForm::Absolute;
+                    uri.scheme = Some(rest[..i].to_string());
+                    rest = &rest[(i + 3)..];
+
+                    let end = rest.find(|c| c == '/' || c == '?' || c == '#').unwrap_or(rest.len());
+                    if end == 0 {
+                        return Err(UriError::InvalidForm);
+                    }
+                    uri.authority = Some(rest[..end].to_string());
+                    rest = &rest[end..];
+                },
+                _ => {
+                    // host:port, nothing else is allowed
+                    let sep = rest.rfind(':');
+                    if sep.is_none() || sep.unwrap() == 0 || rest.contains(|c| c == '/' || c == '?' || c == '#') {
+                        return Err(UriError::InvalidForm);
+                    }
+                    if !rest[(sep.unwrap() + 1)..].bytes().all(|b| b >= b'0' && b <= b'9') {
+                        return Err(UriError::InvalidForm);
+                    }
+
+                    uri.form = RequestTargetForm::Authority;
+                    uri.authority = Some(rest.to_string());
+                    return Ok(uri);
+                }
+            }
+        }
+
+        if let Some(i) = rest.find('#') {
+            uri.fragment = Some(rest[(i + 1)..].to_string());
+            rest = &rest[..i];
+        }
+        if let Some(i) = rest.find('?') {
+            uri.query = Some(rest[(i + 1)..].to_string());
+            rest = &rest[..i];
+        }
+
+        // an absolute URL may leave out the path, it's the root then
+        let path = if rest.len() == 0 { "/" } else { rest };
+        uri.path = path.to_string();
+        for segment in path[1..].split('/') {
+            let decoded = percent_decode(segment.as_bytes());
+            match from_utf8(&decoded) {
+                Ok(s) => uri.segments.push(s.to_string()),
+                Err(_) => return Err(UriError::InvalidPath)
+            }
+        }
+
+        Ok(uri)
+    }
+
+    /// The decoded `key=value` pairs of the query.
+    pub fn query_params(&self) -> BTreeMap<String, String> {
+        match self.query {
+            Some(ref q) => parse_urlencoded_form(q),
+            None => BTreeMap::new()
+        }
+    }
+}
+
+/// `ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )`, RFC 3986 section 3.1.
+fn is_scheme(s: &str) -> bool {
+    let b = s.as_bytes();
+    let alpha = |c: u8| (c >= b'a' && c <= b'z') || (c >= b'A' && c <= b'Z');
+
+    alpha(b[0]) && b.iter().all(|&c| alpha(c) || (c >= b'0' && c <= b'9') || c == b'+' || c == b'-' || c == b'.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collections::vec::*;
+    use collections::String;
+
+    #[test]
+    pub fn test_uri_forms() {
+        let u = Uri::parse("/form?ssid=a%20b&x=1#top").unwrap();
+        assert_eq!(u.form, RequestTargetForm::Origin);
+        assert_eq!(u.path, "/form");
+        assert_eq!(u.segments, vec![String::from("form")]);
+        assert_eq!(u.query, Some(String::from("ssid=a%20b&x=1")));
+        assert_eq!(u.fragment, Some(String::from("top")));
+        assert_eq!(u.query_params().get("ssid").unwrap(), "a b");
+
+        let u = Uri::parse("/caf%C3%A9/a%2Fb/").unwrap();
+        assert_eq!(u.path, "/caf%C3%A9/a%2Fb/");
+        assert_eq!(u.segments, vec![String::from("café"), String::from("a/b"), String::new()]);
+        assert_eq!(u.query, None);
+
+        let u = Uri::parse("http://example.com:8080/test/5?x").unwrap();
+        assert_eq!(u.form, RequestTargetForm::Absolute);
+        assert_eq!(u.scheme, Some(String::from("http")));
+        assert_eq!(u.authority, Some(String::from("example.com:8080")));
+        assert_eq!(u.path, "/test/5");
+        assert_eq!(u.query, Some(String::from("x")));
+
+        let u = Uri::parse("http://example.com?x").unwrap();
+        assert_eq!(u.path, "/");
+        assert_eq!(u.segments, vec![String::new()]);
+
+        let u = Uri::parse("example.com:443").unwrap();
+        assert_eq!(u.form, RequestTargetForm::Authority);
+        assert_eq!(u.authority, Some(String::from("example.com:443")));
+        assert_eq!(u.path, "");
+        assert_eq!(u.segments.len(), 0);
+
+        let u = Uri::parse("*").unwrap();
+        assert_eq!(u.form, RequestTargetForm::Asterisk);
+
+        assert_eq!(Uri::parse(""), Err(UriError::Empty));
+        assert_eq!(Uri::parse("form"), Err(UriError::InvalidForm));
+        assert_eq!(Uri::parse("example.com:443/x"), Err(UriError::InvalidForm));
+        assert_eq!(Uri::parse("http:///x"), Err(UriError::InvalidForm));
+        assert_eq!(Uri::parse("/%FF"), Err(UriError::InvalidPath));
+    }
+}